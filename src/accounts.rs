@@ -0,0 +1,671 @@
+use std::convert::Infallible;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Duration, Utc};
+use dotenvy::var;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::{Cookie, CookieJar, Method, SameSite, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use scrypt::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use scrypt::{Params, Scrypt};
+use utoipa::ToSchema;
+
+use crate::keys::{self, Principal, Scope};
+use crate::totp::SecondFactors;
+use crate::{persistence, ApiKey, ApiKeys, RateLimitGuard, SharedEvents};
+
+const ACCOUNTS_FILE: &str = "accounts.json";
+pub const SESSION_COOKIE: &str = "adharva_session";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+const SESSION_IDLE_DAYS: i64 = 14; // a session left unused this long has to log in again
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60; // last_seen_at is only written to disk this often per session
+const MIN_PASSWORD_CHARS: usize = 10;
+
+// OWASP's scrypt setting for 32 MiB per hash. Verification reads the parameters back from each
+// stored hash, so raising these only affects passwords set afterwards
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Account {
+    id: String,
+    username: String, // recorded as the author of changes made while logged in
+    password_hash: String, // scrypt, in PHC string format
+    scopes: Vec<Scope>, // the same "admin" and "event:<name>" scopes API keys carry
+    created_at: DateTime<Utc>,
+    password_changed_at: DateTime<Utc>,
+}
+
+impl Account {
+    fn info(&self, sessions: usize) -> AccountInfo {
+        AccountInfo {
+            id: self.id.clone(),
+            identity: self.username.clone(),
+            admin: self.scopes.contains(&Scope::Admin),
+            events: self
+                .scopes
+                .iter()
+                .filter_map(|scope| match scope {
+                    Scope::Event(event) => Some(event.clone()),
+                    Scope::Admin => None,
+                })
+                .collect(),
+            scopes: self.scopes.iter().map(Scope::to_string).collect(),
+            created_at: self.created_at,
+            password_changed_at: self.password_changed_at,
+            sessions,
+        }
+    }
+}
+
+// Only a hash of the cookie value is kept, so a copy of accounts.json cannot be used to log in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Session {
+    id: String,
+    user_id: String,
+    token_hash: String,
+    csrf_token: String, // sent back in X-CSRF-Token with every write made with the cookie
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    #[serde(default)]
+    stepped_up_until: Option<DateTime<Utc>>, // admin actions are allowed until then, see /api/v3/2fa/verify
+}
+
+impl Session {
+    fn expires_at(&self) -> DateTime<Utc> {
+        self.last_seen_at + Duration::days(SESSION_IDLE_DAYS)
+    }
+
+    fn info(&self, username: &str, current: Option<&str>) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            username: username.to_string(),
+            created_at: self.created_at,
+            last_seen_at: self.last_seen_at,
+            expires_at: self.expires_at(),
+            user_agent: self.user_agent.clone(),
+            ip: self.ip.clone(),
+            current: current == Some(self.id.as_str()),
+        }
+    }
+}
+
+fn random_token(length: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(length).map(char::from).collect()
+}
+
+fn hash_password(password: &str) -> Result<String, Status> {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(|_| Status::InternalServerError)?;
+    let params = Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, Params::RECOMMENDED_LEN).map_err(|_| Status::InternalServerError)?;
+    Scrypt
+        .hash_password_customized(password.as_bytes(), None, None, params, &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| Status::InternalServerError)
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| Scrypt.verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+// Checked against when the username is unknown, so a failed login takes as long either way
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| hash_password(&random_token(MIN_PASSWORD_CHARS)).unwrap_or_default())
+}
+
+// Hashing is deliberately slow, so it runs off the async workers
+async fn hash_password_blocking(password: String) -> Result<String, Status> {
+    spawn_blocking(move || hash_password(&password)).await.map_err(|_| Status::InternalServerError)?
+}
+
+async fn verify_password_blocking(password: String, hash: String) -> bool {
+    spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false)
+}
+
+fn valid_password(password: &str) -> bool {
+    password.chars().count() >= MIN_PASSWORD_CHARS
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct AccountStore {
+    users: Vec<Account>,
+    sessions: Vec<Session>,
+}
+
+impl AccountStore {
+    fn save(&mut self) -> Result<(), Status> {
+        let now = Utc::now();
+        self.sessions.retain(|session| session.expires_at() > now);
+        persistence::save(ACCOUNTS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    fn user(&self, id: &str) -> Option<&Account> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
+    fn username(&self, id: &str) -> &str {
+        self.user(id).map_or("unknown", |user| user.username.as_str())
+    }
+
+    fn session_count(&self, user_id: &str, now: DateTime<Utc>) -> usize {
+        self.sessions.iter().filter(|session| session.user_id == user_id && session.expires_at() > now).count()
+    }
+
+    fn info(&self, user: &Account) -> AccountInfo {
+        user.info(self.session_count(&user.id, Utc::now()))
+    }
+
+    // Ends every session of a user, except `keep` when given
+    fn end_sessions(&mut self, user_id: &str, keep: Option<&str>) {
+        self.sessions.retain(|session| session.user_id != user_id || Some(session.id.as_str()) == keep);
+    }
+}
+
+pub struct Accounts {
+    store: Mutex<AccountStore>,
+}
+
+impl Accounts {
+    pub fn load() -> Self {
+        Accounts { store: Mutex::new(persistence::load(ACCOUNTS_FILE).unwrap_or_default()) }
+    }
+
+    pub fn info(&self, user_id: &str) -> Option<AccountInfo> {
+        let store = self.store.lock().unwrap();
+        store.user(user_id).map(|user| store.info(user))
+    }
+
+    // Resolves a session cookie for the ApiKey guard. The error names the metrics reason
+    pub fn authenticate(&self, token: &str, req: &Request<'_>) -> Result<ApiKey, (Status, &'static str)> {
+        let now = Utc::now();
+        let token_hash = keys::hash(token);
+
+        let mut store = self.store.lock().unwrap();
+        let session = store
+            .sessions
+            .iter()
+            .find(|session| session.token_hash == token_hash)
+            .ok_or((Status::Unauthorized, "invalid_session"))?;
+        if session.expires_at() <= now {
+            return Err((Status::Unauthorized, "expired_session"));
+        }
+
+        // Cookies are sent by the browser on its own, so writes also need the token only the page knows
+        if !matches!(req.method(), Method::Get | Method::Head | Method::Options)
+            && req.headers().get_one(CSRF_HEADER) != Some(session.csrf_token.as_str())
+        {
+            return Err((Status::Forbidden, "csrf_mismatch"));
+        }
+
+        let user = store.user(&session.user_id).ok_or((Status::Unauthorized, "invalid_session"))?;
+        let principal = Principal::Account { user: user.id.clone(), session: session.id.clone() };
+        let stepped_up = session.stepped_up_until.is_some_and(|until| until > now);
+        let api_key = ApiKey::new(principal, user.username.clone(), user.scopes.clone()).with_second_factor(stepped_up);
+
+        let session = store.sessions.iter_mut().find(|session| session.token_hash == token_hash).unwrap();
+        let stale = now - session.last_seen_at >= Duration::seconds(LAST_SEEN_RESOLUTION_SECONDS);
+        session.last_seen_at = now;
+        if stale {
+            // Losing a last-seen timestamp is not worth failing the request over
+            let _ = store.save();
+        }
+
+        Ok(api_key)
+    }
+
+    // Lets a session act as admin for a while after its second factor was checked
+    pub fn step_up(&self, session_id: &str, until: DateTime<Utc>) -> Result<DateTime<Utc>, Status> {
+        let mut store = self.store.lock().unwrap();
+        let session = store.sessions.iter_mut().find(|session| session.id == session_id).ok_or(Status::Unauthorized)?;
+        session.stepped_up_until = Some(until);
+        store.save()?;
+        Ok(until)
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(ACCOUNTS_FILE, &*self.store.lock().unwrap())
+    }
+}
+
+// Where a login came from, shown in the session list so a lost phone can be recognised
+pub struct Device {
+    user_agent: Option<String>,
+    ip: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Device {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Device {
+            user_agent: req.headers().get_one("User-Agent").map(str::to_string),
+            ip: req.client_ip().map(|ip| ip.to_string()),
+        })
+    }
+}
+
+// Secure when served over TLS here or behind a proxy that terminates it
+fn secure_cookie(req_tls: bool) -> bool {
+    req_tls || var("PUBLIC_URL").is_ok_and(|url| url.starts_with("https://"))
+}
+
+fn session_of(api_key: &ApiKey) -> Option<(&str, &str)> {
+    match &api_key.principal {
+        Principal::Account { user, session } => Some((user, session)),
+        Principal::Key(_) | Principal::Token { .. } => None,
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountInfo {
+    id: String,
+    identity: String, // the username
+    admin: bool,
+    events: Vec<String>, // events this account may edit, empty for admins who may edit all
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    password_changed_at: DateTime<Utc>,
+    sessions: usize, // sessions currently logged in
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SessionInfo {
+    id: String,
+    username: String,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>, // moves forward with every use
+    user_agent: Option<String>,
+    ip: Option<String>,
+    current: bool, // the session making this request
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct LoggedIn {
+    account: AccountInfo,
+    session: SessionInfo,
+    csrf_token: String, // send as X-CSRF-Token on every request that is not a GET
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Login {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewAccount {
+    username: String,
+    password: String,
+    #[schema(value_type = Vec<String>, example = json!(["event:Yukti"]))]
+    scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordReset {
+    password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+// Sets an HttpOnly session cookie for the dashboard. Failures are rate limited along with the rest
+#[utoipa::path(
+    post, path = "/api/v3/login", tag = "accounts", request_body = Login,
+    responses((status = 200, description = "Logged in, the session cookie is set", body = LoggedIn), (status = 401, description = "Unknown username or wrong password"), (status = 429))
+)]
+#[post("/api/v3/login", data = "<request>")]
+async fn login(
+    request: Json<Login>,
+    accounts: &State<Accounts>,
+    cookies: &CookieJar<'_>,
+    device: Device,
+    rocket_config: &rocket::Config,
+    _limitguard: RocketGovernor<'_, RateLimitGuard>
+) -> Result<Json<LoggedIn>, Status> {
+    let request = request.into_inner();
+    let username = request.username.trim();
+
+    let user = accounts.store.lock().unwrap().users.iter().find(|user| user.username.eq_ignore_ascii_case(username)).cloned();
+    let hash = user.as_ref().map_or_else(|| dummy_hash().to_string(), |user| user.password_hash.clone());
+    let verified = verify_password_blocking(request.password, hash).await;
+
+    let Some(user) = user.filter(|_| verified) else {
+        crate::metrics::METRICS.auth_failure("bad_password");
+        return Err(Status::Unauthorized);
+    };
+
+    let now = Utc::now();
+    let token = random_token(48);
+    let session = Session {
+        id: random_token(12),
+        user_id: user.id.clone(),
+        token_hash: keys::hash(&token),
+        csrf_token: random_token(32),
+        created_at: now,
+        last_seen_at: now,
+        user_agent: device.user_agent,
+        ip: device.ip,
+        stepped_up_until: None,
+    };
+
+    let session_info = session.info(&user.username, Some(&session.id));
+    let csrf_token = session.csrf_token.clone();
+    let mut store = accounts.store.lock().unwrap();
+    store.sessions.push(session);
+    store.save()?;
+    let logged_in = LoggedIn { account: store.info(&user), session: session_info, csrf_token };
+
+    cookies.add(
+        Cookie::build((SESSION_COOKIE, token))
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .secure(secure_cookie(rocket_config.tls_enabled()))
+            .max_age(rocket::time::Duration::days(SESSION_IDLE_DAYS))
+    );
+
+    Ok(Json(logged_in))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/logout", tag = "accounts",
+    responses((status = 204, description = "The session is ended and its cookie removed"), (status = 400, description = "Authenticated with an API key, not a session"), (status = 401), (status = 403, description = "Missing or wrong X-CSRF-Token")),
+    security(("session" = []))
+)]
+#[post("/api/v3/logout")]
+fn logout(api_key: ApiKey, accounts: &State<Accounts>, cookies: &CookieJar<'_>) -> Result<Status, Status> {
+    let (_, session_id) = session_of(&api_key).ok_or(Status::BadRequest)?;
+
+    let mut store = accounts.store.lock().unwrap();
+    store.sessions.retain(|session| session.id != session_id);
+    store.save()?;
+    cookies.remove(Cookie::from(SESSION_COOKIE));
+
+    Ok(Status::NoContent)
+}
+
+// Lets a reloaded page pick up the CSRF token of the cookie it still has
+#[utoipa::path(
+    get, path = "/api/v3/session", tag = "accounts",
+    responses((status = 200, body = LoggedIn), (status = 401), (status = 404, description = "Authenticated with an API key, not a session")),
+    security(("session" = []))
+)]
+#[get("/api/v3/session")]
+fn current_session(api_key: ApiKey, accounts: &State<Accounts>) -> Result<Json<LoggedIn>, Status> {
+    let (user_id, session_id) = session_of(&api_key).ok_or(Status::NotFound)?;
+
+    let store = accounts.store.lock().unwrap();
+    let user = store.user(user_id).ok_or(Status::Unauthorized)?;
+    let session = store.sessions.iter().find(|session| session.id == session_id).ok_or(Status::Unauthorized)?;
+
+    Ok(Json(LoggedIn {
+        account: store.info(user),
+        session: session.info(&user.username, Some(session_id)),
+        csrf_token: session.csrf_token.clone(),
+    }))
+}
+
+// The caller's own sessions, so a coordinator can end one left open on another device
+#[utoipa::path(
+    get, path = "/api/v3/sessions", tag = "accounts",
+    responses((status = 200, description = "Sessions of the logged in account, empty for API keys", body = [SessionInfo]), (status = 401)),
+    security(("session" = []))
+)]
+#[get("/api/v3/sessions")]
+fn list_own_sessions(api_key: ApiKey, accounts: &State<Accounts>) -> Json<Vec<SessionInfo>> {
+    let Some((user_id, session_id)) = session_of(&api_key) else {
+        return Json(Vec::new());
+    };
+
+    let now = Utc::now();
+    let store = accounts.store.lock().unwrap();
+    let username = store.username(user_id);
+    Json(store
+        .sessions
+        .iter()
+        .filter(|session| session.user_id == user_id && session.expires_at() > now)
+        .map(|session| session.info(username, Some(session_id)))
+        .collect())
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/sessions", tag = "accounts",
+    responses((status = 200, description = "Every open session, most recently used first", body = [SessionInfo]), (status = 403)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[get("/api/v3/admin/sessions")]
+fn list_sessions(api_key: ApiKey, api_keys: &State<ApiKeys>, accounts: &State<Accounts>) -> Result<Json<Vec<SessionInfo>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let now = Utc::now();
+    let current = session_of(&api_key).map(|(_, session)| session);
+    let store = accounts.store.lock().unwrap();
+    let mut sessions: Vec<&Session> = store.sessions.iter().filter(|session| session.expires_at() > now).collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+
+    Ok(Json(sessions.into_iter().map(|session| session.info(store.username(&session.user_id), current)).collect()))
+}
+
+// Coordinators may end their own sessions, admins may end anyone's
+#[utoipa::path(
+    delete, path = "/api/v3/sessions/{id}", tag = "accounts",
+    params(("id" = String, Path, description = "Session id")),
+    responses((status = 200, description = "The session, which is logged out immediately", body = SessionInfo), (status = 403), (status = 404)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[delete("/api/v3/sessions/<id>")]
+fn end_session(id: &str, api_key: ApiKey, api_keys: &State<ApiKeys>, accounts: &State<Accounts>) -> Result<Json<SessionInfo>, Status> {
+    let current = session_of(&api_key);
+    let mut store = accounts.store.lock().unwrap();
+    let session = store.sessions.iter().find(|session| session.id == id).ok_or(Status::NotFound)?;
+    let own = current.is_some_and(|(user_id, _)| user_id == session.user_id);
+    if !own && !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let info = session.info(store.username(&session.user_id), current.map(|(_, session)| session));
+    store.sessions.retain(|session| session.id != id);
+    store.save()?;
+
+    Ok(Json(info))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/account/password", tag = "accounts", request_body = PasswordChange,
+    responses((status = 204, description = "Password changed, other sessions of the account are logged out"), (status = 400, description = "Authenticated with an API key, not a session"), (status = 403, description = "Wrong current password"), (status = 422, description = "New password too short")),
+    security(("session" = []))
+)]
+#[post("/api/v3/account/password", data = "<request>")]
+async fn change_password(request: Json<PasswordChange>, api_key: ApiKey, accounts: &State<Accounts>) -> Result<Status, Status> {
+    let (user_id, session_id) = session_of(&api_key).ok_or(Status::BadRequest)?;
+    let request = request.into_inner();
+    if !valid_password(&request.new_password) {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let current_hash = accounts.store.lock().unwrap().user(user_id).ok_or(Status::Unauthorized)?.password_hash.clone();
+    if !verify_password_blocking(request.current_password, current_hash).await {
+        crate::metrics::METRICS.auth_failure("bad_password");
+        return Err(Status::Forbidden);
+    }
+    let password_hash = hash_password_blocking(request.new_password).await?;
+
+    let mut store = accounts.store.lock().unwrap();
+    let user = store.users.iter_mut().find(|user| user.id == user_id).ok_or(Status::Unauthorized)?;
+    user.password_hash = password_hash;
+    user.password_changed_at = Utc::now();
+    store.end_sessions(user_id, Some(session_id));
+    store.save()?;
+
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/users", tag = "accounts",
+    responses((status = 200, body = [AccountInfo]), (status = 403)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[get("/api/v3/admin/users")]
+fn list_users(api_key: ApiKey, api_keys: &State<ApiKeys>, accounts: &State<Accounts>) -> Result<Json<Vec<AccountInfo>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let store = accounts.store.lock().unwrap();
+    let mut users: Vec<AccountInfo> = store.users.iter().map(|user| store.info(user)).collect();
+    users.sort_by(|a, b| (!a.admin, &a.identity).cmp(&(!b.admin, &b.identity)));
+
+    Ok(Json(users))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/users", tag = "accounts", request_body = NewAccount,
+    responses((status = 200, description = "The new account", body = AccountInfo), (status = 403), (status = 409, description = "The username is taken"), (status = 422, description = "Empty username or scopes, an unknown event, or a password under 10 characters")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/admin/users", data = "<request>")]
+async fn create_user(
+    request: Json<NewAccount>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    accounts: &State<Accounts>,
+    events: &State<SharedEvents>
+) -> Result<Json<AccountInfo>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let request = request.into_inner();
+    let username = request.username.trim().to_string();
+    if username.is_empty() || !valid_password(&request.password) || !keys::valid_scopes(&request.scopes, events) {
+        return Err(Status::UnprocessableEntity);
+    }
+    let password_hash = hash_password_blocking(request.password).await?;
+
+    let mut store = accounts.store.lock().unwrap();
+    if store.users.iter().any(|user| user.username.eq_ignore_ascii_case(&username)) {
+        return Err(Status::Conflict);
+    }
+
+    let now = Utc::now();
+    let user = Account {
+        id: random_token(12),
+        username,
+        password_hash,
+        scopes: request.scopes,
+        created_at: now,
+        password_changed_at: now,
+    };
+    let info = user.info(0);
+    store.users.push(user);
+    store.save()?;
+
+    Ok(Json(info))
+}
+
+// Sets a new password and logs the account out everywhere
+#[utoipa::path(
+    post, path = "/api/v3/admin/users/{id}/password", tag = "accounts", request_body = PasswordReset,
+    params(("id" = String, Path, description = "Account id")),
+    responses((status = 200, body = AccountInfo), (status = 403), (status = 404), (status = 422, description = "Password under 10 characters")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/admin/users/<id>/password", data = "<request>")]
+async fn reset_password(
+    id: &str,
+    request: Json<PasswordReset>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    accounts: &State<Accounts>
+) -> Result<Json<AccountInfo>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    if accounts.store.lock().unwrap().user(id).is_none() {
+        return Err(Status::NotFound);
+    }
+
+    let request = request.into_inner();
+    if !valid_password(&request.password) {
+        return Err(Status::UnprocessableEntity);
+    }
+    let password_hash = hash_password_blocking(request.password).await?;
+
+    let mut store = accounts.store.lock().unwrap();
+    let user = store.users.iter_mut().find(|user| user.id == id).ok_or(Status::NotFound)?;
+    user.password_hash = password_hash;
+    user.password_changed_at = Utc::now();
+    store.end_sessions(id, None);
+    store.save()?;
+
+    let user = store.user(id).ok_or(Status::NotFound)?;
+    Ok(Json(store.info(user)))
+}
+
+// Deleting an account logs it out everywhere. Past changes keep its username as their author
+#[utoipa::path(
+    delete, path = "/api/v3/admin/users/{id}", tag = "accounts",
+    params(("id" = String, Path, description = "Account id")),
+    responses((status = 200, description = "The deleted account", body = AccountInfo), (status = 403), (status = 404)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[delete("/api/v3/admin/users/<id>")]
+fn delete_user(
+    id: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    accounts: &State<Accounts>,
+    second_factors: &State<SecondFactors>
+) -> Result<Json<AccountInfo>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = accounts.store.lock().unwrap();
+    let index = store.users.iter().position(|user| user.id == id).ok_or(Status::NotFound)?;
+    let user = store.users.remove(index);
+    store.end_sessions(id, None);
+    store.save()?;
+    second_factors.remove_account(id)?;
+
+    Ok(Json(user.info(0)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        login,
+        logout,
+        current_session,
+        list_own_sessions,
+        list_sessions,
+        end_session,
+        change_password,
+        list_users,
+        create_user,
+        reset_password,
+        delete_user
+    ]
+}
@@ -0,0 +1,202 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use utoipa::ToSchema;
+
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{persistence, ApiKey, ApiKeys, RateLimitGuard, SharedEvents};
+
+const ANNOUNCEMENTS_FILE: &str = "announcements.json";
+
+pub type SharedAnnouncements = Mutex<AnnouncementBoard>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Announcement {
+    pub id: String,
+    pub message: String,
+    pub priority: Priority,
+    #[serde(default)]
+    pub events: Vec<String>, // empty means fest-wide
+    pub author: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Announcement {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expiry| expiry > now)
+    }
+
+    pub fn concerns(&self, event_name: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event_name)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AnnouncementBoard {
+    announcements: Vec<Announcement>,
+}
+
+impl AnnouncementBoard {
+    pub fn load() -> Self {
+        persistence::load(ANNOUNCEMENTS_FILE).unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(ANNOUNCEMENTS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    // Most urgent first, newest first within the same priority
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(ANNOUNCEMENTS_FILE, self)
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = &Announcement> {
+        let now = Utc::now();
+        let mut active: Vec<&Announcement> =
+            self.announcements.iter().filter(|a| a.is_active(now)).collect();
+        active.sort_by(|a, b| b.priority.cmp(&a.priority).then(b.created_at.cmp(&a.created_at)));
+        active.into_iter()
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewAnnouncement {
+    message: String,
+    #[serde(default = "default_priority")]
+    priority: Priority,
+    #[serde(default)]
+    events: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+fn default_priority() -> Priority {
+    Priority::Normal
+}
+
+// Coordinators may only announce for their own events, fest-wide notices need the root key
+fn can_author(api_keys: &ApiKeys, api_key: &ApiKey, events: &[String]) -> bool {
+    if api_keys.is_root(api_key) {
+        return true;
+    }
+    !events.is_empty() && events.iter().all(|event| api_keys.can_edit(api_key, event))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/announcements", tag = "announcements",
+    params(("event" = Option<String>, Query, description = "Only announcements concerning this event")),
+    responses((status = 200, description = "Active announcements, most urgent first", body = [Announcement]))
+)]
+#[get("/api/v3/announcements?<event>")]
+fn list_announcements(
+    event: Option<&str>,
+    board: &State<SharedAnnouncements>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Json<Vec<Announcement>> {
+    let board = board.lock().unwrap();
+    Json(
+        board
+            .active()
+            .filter(|a| event.is_none_or(|event| a.concerns(event)))
+            .cloned()
+            .collect(),
+    )
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/announcements", tag = "announcements", request_body = NewAnnouncement,
+    responses((status = 200, body = Announcement), (status = 403), (status = 404), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/announcements", data = "<announcement>")]
+fn create_announcement(
+    announcement: Json<NewAnnouncement>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>,
+    board: &State<SharedAnnouncements>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<Announcement>, Status> {
+    let announcement = announcement.into_inner();
+
+    if !can_author(api_keys, &api_key, &announcement.events) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    if announcement.message.trim().is_empty()
+        || announcement.expires_at.is_some_and(|expiry| expiry <= Utc::now())
+    {
+        return Err(Status::UnprocessableEntity);
+    }
+    {
+        let events = events.lock().unwrap();
+        if !announcement.events.iter().all(|name| events.iter().any(|e| e.name == *name)) {
+            return Err(Status::NotFound);
+        }
+    }
+
+    let created = Announcement {
+        id: rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect(),
+        message: announcement.message.trim().to_string(),
+        priority: announcement.priority,
+        events: announcement.events,
+        author: api_keys.identity(&api_key),
+        created_at: Utc::now(),
+        expires_at: announcement.expires_at,
+    };
+
+    let mut board = board.lock().unwrap();
+    board.announcements.push(created.clone());
+    board.save()?;
+
+    updates.publish(LiveUpdate::Announcement { announcement: created.clone() });
+    Ok(Json(created))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/announcements/{id}", tag = "announcements",
+    params(("id" = String, Path, description = "Announcement id")),
+    responses((status = 200, body = Announcement), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/announcements/<id>")]
+fn delete_announcement(
+    id: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    board: &State<SharedAnnouncements>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<Announcement>, Status> {
+    let mut board = board.lock().unwrap();
+    let index = board.announcements.iter().position(|a| a.id == id).ok_or(Status::NotFound)?;
+
+    if !can_author(api_keys, &api_key, &board.announcements[index].events) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let removed = board.announcements.remove(index);
+    board.save()?;
+
+    updates.publish(LiveUpdate::AnnouncementRemoved { id: removed.id.clone() });
+    Ok(Json(removed))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![list_announcements, create_announcement, delete_announcement]
+}
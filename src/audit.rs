@@ -0,0 +1,126 @@
+use std::fs;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Response};
+use tracing::warn;
+
+use crate::{logging, persistence};
+
+const AUDIT_FILE: &str = "audit.jsonl";
+const ROTATED_AUDIT_FILE: &str = "audit.jsonl.1";
+// Written by earlier versions as one JSON document, read once to seed the line log
+const LEGACY_AUDIT_FILE: &str = "audit.json";
+// The current file is renamed over the rotated one when it grows past this
+const ROTATE_BYTES: u64 = 8 * 1024 * 1024;
+
+pub type SharedAudit = Mutex<AuditLog>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub request_id: String,
+    pub actor: Option<String>, // identity behind the API key, None when no valid key was presented
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct LegacyAuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+// Entries from the current and the rotated file, so memory is bounded by the rotation size too
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>, // oldest first
+    rotated: usize,           // how many of the entries came from the rotated file
+    current_bytes: u64,
+}
+
+impl AuditLog {
+    pub fn load() -> Self {
+        let rotated: Vec<AuditEntry> = persistence::load_lines(ROTATED_AUDIT_FILE);
+        let mut log = AuditLog {
+            rotated: rotated.len(),
+            entries: rotated,
+            current_bytes: fs::metadata(AUDIT_FILE).map_or(0, |meta| meta.len()),
+        };
+        log.entries.extend(persistence::load_lines::<AuditEntry>(AUDIT_FILE));
+
+        if log.entries.is_empty()
+            && let Some(legacy) = persistence::load::<LegacyAuditLog>(LEGACY_AUDIT_FILE)
+        {
+            for entry in legacy.entries {
+                if let Err(error) = log.append(entry) {
+                    warn!(%error, "failed to migrate audit log");
+                    break;
+                }
+            }
+        }
+        log
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    fn append(&mut self, entry: AuditEntry) -> std::io::Result<()> {
+        if self.current_bytes >= ROTATE_BYTES {
+            fs::rename(AUDIT_FILE, ROTATED_AUDIT_FILE)?;
+            self.entries.drain(..self.rotated);
+            self.rotated = self.entries.len();
+            self.current_bytes = 0;
+        }
+
+        self.current_bytes += persistence::append_line(AUDIT_FILE, &entry)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+// Set by the ApiKey guard once a key has been accepted
+pub struct Actor(pub Option<String>);
+
+// Records every authenticated request that could change state, successful or not. Anonymous requests
+// are left to the access log, so nobody can grow the audit log without a key. Bodies are never stored
+pub struct AuditTrail;
+
+#[rocket::async_trait]
+impl Fairing for AuditTrail {
+    fn info(&self) -> Info {
+        Info { name: "Audit trail", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if matches!(req.method(), Method::Get | Method::Head | Method::Options) {
+            return;
+        }
+        let Some(actor) = req.local_cache(|| Actor(None)).0.clone() else {
+            return;
+        };
+        let Some(audit) = req.rocket().state::<SharedAudit>() else {
+            return;
+        };
+
+        let entry = AuditEntry {
+            at: Utc::now(),
+            request_id: logging::request_span(req).id.clone(),
+            actor: Some(actor),
+            method: req.method().as_str().to_string(),
+            path: req.uri().path().to_string(),
+            status: res.status().code,
+        };
+
+        // A single appended line, so holding the lock across the write stays cheap
+        if let Err(error) = audit.lock().unwrap().append(entry) {
+            warn!(%error, "failed to persist audit log");
+        }
+    }
+}
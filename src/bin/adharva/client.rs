@@ -0,0 +1,176 @@
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+
+use reqwest::blocking::{Client as HttpClient, RequestBuilder, Response};
+use reqwest::{StatusCode, Url};
+use serde_json::Value;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+pub struct Client {
+    server: Url,
+    key: Option<String>,
+    otp: Option<String>,
+    http: HttpClient,
+}
+
+fn describe_status(status: StatusCode) -> String {
+    match status.as_u16() {
+        401 => "the server did not recognise the API key".to_string(),
+        403 => "this API key is not allowed to do that".to_string(),
+        404 => "not found".to_string(),
+        409 => "the request conflicts with the current state on the server".to_string(),
+        423 => "event statuses are locked by an emergency override".to_string(),
+        429 => "rate limited by the server, try again in a moment".to_string(),
+        _ => format!("server responded with {}", status),
+    }
+}
+
+// Admin refusals say whether a code is missing or no second factor is enrolled yet
+fn describe_response(response: &Response) -> String {
+    match response.headers().get("X-OTP-Required").and_then(|value| value.to_str().ok()) {
+        Some("enroll") => "admin actions need a second factor, enrol one with `adharva 2fa enroll`".to_string(),
+        Some("locked") => "too many wrong codes, the second factor is locked for a while, try again later".to_string(),
+        Some(_) => "admin actions need a fresh code from your authenticator app, pass --otp (each code works once)".to_string(),
+        None => describe_status(response.status()),
+    }
+}
+
+impl Client {
+    pub fn new(server: String, key: Option<String>, otp: Option<String>) -> Result<Self, String> {
+        let http = HttpClient::builder()
+            .timeout(None::<Duration>)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let server = Url::parse(server.trim())
+            .ok()
+            .filter(|url| !url.cannot_be_a_base() && matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| format!("'{}' is not an http(s) server URL", server))?;
+
+        Ok(Client { server, key, otp, http })
+    }
+
+    // Spends the --otp code on an access token that stays stepped up for a while, for commands that
+    // make several admin calls since each code only works once
+    pub fn stepped_up(&self) -> Result<Client, String> {
+        if self.otp.is_none() {
+            return Ok(self.clone());
+        }
+        let pair = self.post_authorized(self.url("/api/v3/token", &[]), None)?;
+        let token = pair["access_token"].as_str().ok_or("unexpected response: no access token")?;
+        Ok(Client { key: Some(token.to_string()), otp: None, ..self.clone() })
+    }
+
+    // A fixed API path followed by segments that are percent-encoded on the way in, so event names
+    // with spaces, slashes or `#` stay a single segment. Query pairs go on the returned URL
+    pub fn url(&self, path: &str, segments: &[&str]) -> Url {
+        let mut url = self.server.clone();
+        url.path_segments_mut()
+            .expect("checked to be a base URL")
+            .pop_if_empty()
+            .extend(path.split('/').filter(|part| !part.is_empty()))
+            .extend(segments);
+        url
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> Result<RequestBuilder, String> {
+        let request = match &self.key {
+            Some(key) => request.bearer_auth(key),
+            None => return Err("no API key configured, pass --key or set ADHARVA_KEY".to_string()),
+        };
+        Ok(match &self.otp {
+            Some(otp) => request.header("X-OTP", otp),
+            None => request,
+        })
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let response = request
+            .send()
+            .map_err(|e| format!("cannot reach {}: {}", self.server, e))?;
+
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(describe_response(&response))
+        }
+    }
+
+    fn json(&self, request: RequestBuilder) -> Result<Value, String> {
+        self.send(request.timeout(REQUEST_TIMEOUT))?
+            .json()
+            .map_err(|e| format!("unexpected response: {}", e))
+    }
+
+    pub fn get(&self, url: Url) -> Result<Value, String> {
+        self.json(self.http.get(url))
+    }
+
+    pub fn get_authorized(&self, url: Url) -> Result<Value, String> {
+        self.json(self.authorized(self.http.get(url))?)
+    }
+
+    pub fn post_authorized(&self, url: Url, body: Option<&Value>) -> Result<Value, String> {
+        let mut request = self.authorized(self.http.post(url))?;
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        self.json(request)
+    }
+
+    pub fn delete_authorized(&self, url: Url) -> Result<Value, String> {
+        let request = self.authorized(self.http.delete(url))?;
+        self.json(request)
+    }
+
+    // For endpoints that answer 204 No Content
+    pub fn delete_empty(&self, url: Url) -> Result<(), String> {
+        let request = self.authorized(self.http.delete(url))?;
+        self.send(request.timeout(REQUEST_TIMEOUT)).map(drop)
+    }
+
+    // Like post_authorized, but hands back the JSON body of 409 and 422 responses too
+    pub fn post_csv(&self, url: Url, body: String) -> Result<(u16, Value), String> {
+        let response = self
+            .authorized(self.http.post(url))?
+            .header("Content-Type", "text/csv")
+            .body(body)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .map_err(|e| format!("cannot reach {}: {}", self.server, e))?;
+
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::CONFLICT && status != StatusCode::UNPROCESSABLE_ENTITY {
+            return Err(describe_response(&response));
+        }
+        let report = response.json().map_err(|_| describe_status(status))?;
+        Ok((status.as_u16(), report))
+    }
+
+    // Calls back with (event name, JSON data) for every server-sent event until the stream ends
+    pub fn stream(&self, url: Url, mut on_event: impl FnMut(&str, Value)) -> Result<(), String> {
+        let response = self.send(self.http.get(url))?;
+        let mut kind = String::new();
+        let mut data = String::new();
+
+        for line in BufReader::new(response).lines() {
+            let line = line.map_err(|e| format!("stream interrupted: {}", e))?;
+
+            if let Some(value) = line.strip_prefix("event:") {
+                kind = value.trim().to_string();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.trim_start());
+            } else if line.is_empty() && !data.is_empty() {
+                if let Ok(value) = serde_json::from_str(&data) {
+                    on_event(&kind, value);
+                }
+                kind.clear();
+                data.clear();
+            }
+        }
+
+        Ok(())
+    }
+}
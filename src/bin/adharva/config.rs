@@ -0,0 +1,36 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+pub const DEFAULT_SERVER: &str = "http://localhost:10000";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FileConfig {
+    pub server: Option<String>,
+    pub key: Option<String>,
+}
+
+// $XDG_CONFIG_HOME/adharva/config.toml, falling back to ~/.config/adharva/config.toml
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("adharva").join("config.toml"))
+}
+
+// A missing file is fine, an unreadable or malformed one is reported
+pub fn load(path: Option<PathBuf>) -> Result<FileConfig, String> {
+    let explicit = path.is_some();
+    let path = match path.or_else(default_path) {
+        Some(path) => path,
+        None => return Ok(FileConfig::default()),
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(data) => toml::from_str(&data).map_err(|e| format!("invalid config file {}: {}", path.display(), e)),
+        Err(_) if !explicit => Ok(FileConfig::default()),
+        Err(e) => Err(format!("cannot read config file {}: {}", path.display(), e)),
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::client::Client;
+use crate::output::{self, Format};
+
+fn print_report(report: &Value) {
+    let errors = output::rows(&report["errors"], &["row", "column", "message"]);
+    if !errors.is_empty() {
+        output::table(&["row", "column", "error"], &errors);
+        return;
+    }
+
+    let mut rows = Vec::new();
+    for change in report["changes"].as_array().into_iter().flatten() {
+        let kind = output::text(&change["kind"]);
+        let event = output::text(&change["event"]);
+        let fields = change["fields"].as_array().filter(|fields| !fields.is_empty());
+
+        match fields {
+            Some(fields) => rows.extend(fields.iter().map(|field| {
+                vec![
+                    kind.clone(),
+                    event.clone(),
+                    output::text(&field["field"]),
+                    output::text(&field["from"]),
+                    output::text(&field["to"]),
+                ]
+            })),
+            None => rows.push(vec![kind, event, String::new(), String::new(), String::new()]),
+        }
+    }
+
+    if rows.is_empty() {
+        println!("The lineup already matches the file, nothing to change.");
+    } else {
+        output::table(&["change", "event", "field", "from", "to"], &rows);
+    }
+}
+
+fn confirmed(count: usize) -> bool {
+    print!("Apply {} change(s)? [y/N] ", count);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
+
+// Always shows the dry-run diff first; applying reuses its fingerprint so nothing unseen gets applied
+pub fn run(
+    client: &Client,
+    file: &Path,
+    prune: bool,
+    tz: Option<&str>,
+    apply: bool,
+    yes: bool,
+    format: Format
+) -> Result<(), String> {
+    let body = fs::read_to_string(file).map_err(|e| format!("cannot read {}: {}", file.display(), e))?;
+    // The preview and the apply are two admin calls, more than one code covers
+    let stepped_up;
+    let client = if apply {
+        stepped_up = client.stepped_up()?;
+        &stepped_up
+    } else {
+        client
+    };
+
+    let mut url = client.url("/api/v3/admin/import", &[]);
+    url.query_pairs_mut().append_pair("prune", &prune.to_string());
+    if let Some(tz) = tz {
+        url.query_pairs_mut().append_pair("tz", tz);
+    }
+
+    let (status, report) = client.post_csv(url.clone(), body.clone())?;
+    match format {
+        Format::Json if !apply || status != 200 => output::json(&report),
+        Format::Json => (),
+        Format::Table => print_report(&report),
+    }
+    if status == 422 {
+        return Err("the file has errors, nothing was imported".to_string());
+    }
+
+    let changes = report["changes"].as_array().map_or(0, Vec::len);
+    if !apply || changes == 0 {
+        return Ok(());
+    }
+    if !yes && !confirmed(changes) {
+        return Err("import cancelled".to_string());
+    }
+
+    url.query_pairs_mut().append_pair("confirm", &output::text(&report["fingerprint"]));
+    let (status, applied) = client.post_csv(url, body)?;
+    match status {
+        409 => Err("the lineup changed on the server since the preview, run the import again".to_string()),
+        _ => {
+            match format {
+                Format::Json => output::json(&applied),
+                Format::Table => println!("Imported {} change(s).", changes),
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,520 @@
+mod client;
+mod config;
+mod import;
+mod output;
+
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use serde_json::Value;
+
+use client::Client;
+use output::Format;
+
+const STATUSES: [&str; 9] = [
+    "Started", "Ended", "Round1", "Round2", "Round3", "Round4", "Ongoing", "Delayed", "Soon",
+];
+
+/// Command-line client for the Adharva event server
+#[derive(Parser)]
+#[command(name = "adharva", version)]
+struct Cli {
+    /// Server base URL [default: http://localhost:10000]
+    #[arg(long, global = true, env = "ADHARVA_SERVER")]
+    server: Option<String>,
+
+    /// API key, the root key or an event coordinator's key
+    #[arg(long, global = true, env = "ADHARVA_KEY", hide_env_values = true)]
+    key: Option<String>,
+
+    /// Code from your authenticator app (or a backup code), needed for admin actions with the root key
+    #[arg(long, global = true, env = "ADHARVA_OTP", hide_env_values = true)]
+    otp: Option<String>,
+
+    /// Config file holding `server` and `key` [default: ~/.config/adharva/config.toml]
+    #[arg(long, global = true, env = "ADHARVA_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Output format
+    #[arg(long, short, global = true, value_enum, default_value_t = Format::Table)]
+    output: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List events and their current status
+    Events,
+    /// Set the status of an event
+    Set {
+        event: String,
+        /// One of Started, Ended, Round1-Round4, Ongoing, Delayed, Soon (case-insensitive)
+        status: String,
+    },
+    /// Show recent status transitions, newest first
+    History {
+        #[arg(long)]
+        event: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Inspect API keys
+    #[command(subcommand)]
+    Keys(KeysCommand),
+    /// Set up the second factor admin actions need
+    #[command(subcommand, name = "2fa")]
+    TwoFactor(TwoFactorCommand),
+    /// Manage coordinator accounts for the dashboard (root key required)
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// List and end dashboard login sessions (root key required)
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// Follow live updates until interrupted
+    Tail {
+        /// Only show updates for this event
+        #[arg(long)]
+        event: Option<String>,
+    },
+    /// Import the event lineup from a CSV file, showing the changes first (root key required)
+    Import {
+        file: PathBuf,
+        /// Apply the changes after showing them
+        #[arg(long)]
+        apply: bool,
+        /// Don't ask for confirmation before applying
+        #[arg(long, short, requires = "apply")]
+        yes: bool,
+        /// Remove events that are missing from the file
+        #[arg(long)]
+        prune: bool,
+        /// Time zone for times without an offset, e.g. Asia/Kolkata
+        #[arg(long)]
+        tz: Option<String>,
+    },
+    /// Print a shell completion script
+    Completions { shell: Shell },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Show who the configured key belongs to
+    Whoami,
+    /// List keys with their scopes, expiry and last use (root key required)
+    List,
+    /// Issue a new key, its secret is printed once (root key required)
+    Issue {
+        name: String,
+        /// "admin" or "event:<name>", repeat for several
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<String>,
+        /// RFC 3339 time the key stops working, e.g. 2026-03-01T00:00:00Z
+        #[arg(long)]
+        expires_at: Option<String>,
+    },
+    /// Replace a key with a new secret, the old one keeps working for a while
+    Rotate {
+        id: String,
+        /// Hours the old secret stays valid, default 24
+        #[arg(long)]
+        overlap_hours: Option<i64>,
+    },
+    /// Revoke a key immediately (root key required)
+    Revoke { id: String },
+    /// Exchange the configured key for a short-lived access token and a refresh token
+    Token,
+}
+
+#[derive(Subcommand)]
+enum TwoFactorCommand {
+    /// Show whether a second factor is enrolled for the configured key
+    Status,
+    /// Start enrolment, printing a QR code to scan with an authenticator app
+    Enroll,
+    /// Finish enrolment with the first code from the app, printing backup codes
+    Confirm { code: String },
+    /// Replace the backup codes (needs --otp)
+    BackupCodes,
+    /// Remove the second factor of the configured key (needs --otp), or of another key or account as root
+    Disable {
+        /// ID of another key, as shown by `keys list`
+        #[arg(long, conflicts_with = "user")]
+        key_id: Option<String>,
+        /// ID of an account, as shown by `users list`
+        #[arg(long)]
+        user: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// List accounts with their scopes and open sessions
+    List,
+    /// Create an account, the password is asked for interactively
+    Add {
+        username: String,
+        /// "admin" or "event:<name>", repeat for several
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<String>,
+    },
+    /// Set a new password, logging the account out everywhere
+    Passwd { id: String },
+    /// Delete an account and end its sessions
+    Remove { id: String },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List open sessions, most recently used first
+    List,
+    /// Log a session out, e.g. one on a lost phone
+    End { id: String },
+}
+
+fn print_events(events: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(events),
+        Format::Table => output::table(&["event", "status"], &output::rows(events, &["name", "status"])),
+    }
+}
+
+fn print_keys(keys: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(keys),
+        Format::Table => {
+            let keys = match keys {
+                Value::Array(_) => keys.clone(),
+                single => Value::Array(vec![single.clone()]),
+            };
+            output::table(
+                &["id", "identity", "scopes", "state", "expires", "last used"],
+                &output::rows(&keys, &["id", "identity", "scopes", "state", "expires_at", "last_used_at"]),
+            );
+        }
+    }
+}
+
+fn print_users(users: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(users),
+        Format::Table => {
+            let users = match users {
+                Value::Array(_) => users.clone(),
+                single => Value::Array(vec![single.clone()]),
+            };
+            output::table(
+                &["id", "username", "scopes", "sessions", "password changed"],
+                &output::rows(&users, &["id", "identity", "scopes", "sessions", "password_changed_at"]),
+            );
+        }
+    }
+}
+
+fn print_sessions(sessions: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(sessions),
+        Format::Table => {
+            let sessions = match sessions {
+                Value::Array(_) => sessions.clone(),
+                single => Value::Array(vec![single.clone()]),
+            };
+            output::table(
+                &["id", "username", "last seen", "ip", "device"],
+                &output::rows(&sessions, &["id", "username", "last_seen_at", "ip", "user_agent"]),
+            );
+        }
+    }
+}
+
+fn print_tokens(tokens: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(tokens),
+        Format::Table => {
+            println!("access token:  {}", output::text(&tokens["access_token"]));
+            println!("expires:       {}", output::text(&tokens["expires_at"]));
+            println!("refresh token: {}", output::text(&tokens["refresh_token"]));
+            println!("refresh until: {}", output::text(&tokens["refresh_expires_at"]));
+        }
+    }
+}
+
+fn print_enrollment(enrollment: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(enrollment),
+        Format::Table => {
+            let uri = output::text(&enrollment["provisioning_uri"]);
+            if let Ok(code) = qrcode::QrCode::new(uri.as_bytes()) {
+                println!("{}", code.render::<qrcode::render::unicode::Dense1x2>().quiet_zone(true).build());
+            }
+            println!("secret: {}", output::text(&enrollment["secret"]));
+            println!("uri:    {}", uri);
+            println!("Scan the code, then run `adharva 2fa confirm <code>` with the first code it shows.");
+        }
+    }
+}
+
+fn print_backup_codes(codes: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(codes),
+        Format::Table => {
+            for code in codes["codes"].as_array().into_iter().flatten() {
+                println!("{}", output::text(code));
+            }
+            println!("Each backup code works once in place of --otp. Store them now, they cannot be shown again.");
+        }
+    }
+}
+
+// Asks twice without echoing, so a typo does not lock the coordinator out
+fn prompt_password() -> Result<String, String> {
+    let password = rpassword::prompt_password("Password: ").map_err(|e| e.to_string())?;
+    if rpassword::prompt_password("Repeat password: ").map_err(|e| e.to_string())? != password {
+        return Err("passwords do not match".to_string());
+    }
+    Ok(password)
+}
+
+fn print_issued(issued: &Value, format: Format) {
+    match format {
+        Format::Json => output::json(issued),
+        Format::Table => {
+            print_keys(&issued["key"], format);
+            println!();
+            println!("secret: {}", output::text(&issued["secret"]));
+            println!("Store it now, it cannot be shown again.");
+        }
+    }
+}
+
+// One line per update, suitable for watching in a terminal
+fn describe_update(kind: &str, update: &Value) -> Option<String> {
+    match kind {
+        "status_changed" => Some(format!(
+            "{}: {} -> {} (by {})",
+            output::text(&update["event"]),
+            output::text(&update["from"]),
+            output::text(&update["to"]),
+            output::text(&update["by"])
+        )),
+        "announcement" => Some(format!(
+            "announcement [{}]: {}",
+            output::text(&update["announcement"]["priority"]),
+            output::text(&update["announcement"]["message"])
+        )),
+        "event_added" => Some(format!(
+            "{} added to the lineup (by {})",
+            output::text(&update["event"]),
+            output::text(&update["by"])
+        )),
+        "event_removed" => Some(format!(
+            "{} removed from the lineup (by {})",
+            output::text(&update["event"]),
+            output::text(&update["by"])
+        )),
+        "announcement_removed" => Some(format!("announcement removed: {}", output::text(&update["id"]))),
+        "registrations_changed" => Some(format!(
+            "{}: {}, {} registered, {} checked in",
+            output::text(&update["event"]),
+            output::text(&update["change"]).replace('_', " "),
+            output::text(&update["counts"]["registered"]),
+            output::text(&update["counts"]["checked_in"])
+        )),
+        "emergency" => Some(match &update["message"] {
+            Value::Null => "emergency cleared".to_string(),
+            message => format!("EMERGENCY: {}", output::text(message)),
+        }),
+        "restarting" => Some(format!(
+            "server restarting, reconnect in {}s",
+            output::text(&update["retry_after_seconds"])
+        )),
+        _ => None,
+    }
+}
+
+fn mentions_event(kind: &str, update: &Value, event: &str) -> bool {
+    match kind {
+        "status_changed" | "event_added" | "event_removed" | "registrations_changed" => update["event"] == event,
+        "announcement" => update["announcement"]["events"]
+            .as_array()
+            .is_none_or(|events| events.is_empty() || events.iter().any(|e| e == event)),
+        _ => true,
+    }
+}
+
+fn tail(client: &Client, event: Option<&str>, format: Format) -> Result<(), String> {
+    // Every change is followed by a full events frame, only the initial snapshot is worth a table
+    let mut snapshot_shown = false;
+
+    client.stream(client.url("/api/v3/stream", &[]), |kind, update| {
+        if event.is_some_and(|event| !mentions_event(kind, &update, event)) {
+            return;
+        }
+
+        match format {
+            Format::Json => println!("{}", update),
+            Format::Table => {
+                if kind == "events" {
+                    if snapshot_shown {
+                        return;
+                    }
+                    snapshot_shown = true;
+
+                    let events = match event {
+                        Some(event) => Value::Array(
+                            update["events"]
+                                .as_array()
+                                .into_iter()
+                                .flatten()
+                                .filter(|e| e["name"] == event)
+                                .cloned()
+                                .collect(),
+                        ),
+                        None => update["events"].clone(),
+                    };
+                    print_events(&events, format);
+                    println!();
+                } else if let Some(line) = describe_update(kind, &update) {
+                    println!("{} {}", chrono::Local::now().format("%H:%M:%S"), line);
+                }
+            }
+        }
+    })
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    if let Command::Completions { shell } = cli.command {
+        clap_complete::generate(shell, &mut Cli::command(), "adharva", &mut io::stdout());
+        return Ok(());
+    }
+
+    // Flags and environment variables win over the config file
+    let file = config::load(cli.config)?;
+    let server = cli
+        .server
+        .or(file.server)
+        .unwrap_or_else(|| config::DEFAULT_SERVER.to_string());
+    let client = Client::new(server, cli.key.or(file.key), cli.otp)?;
+    let format = cli.output;
+
+    match cli.command {
+        Command::Events => print_events(&client.get(client.url("/api/v3/get/events", &[]))?, format),
+        Command::Set { event, status } => {
+            let status = STATUSES
+                .iter()
+                .find(|s| s.eq_ignore_ascii_case(&status))
+                .ok_or_else(|| format!("unknown status '{}', expected one of: {}", status, STATUSES.join(", ")))?;
+            let events = client.post_authorized(client.url("/api/v3/update", &[&event, status]), None)?;
+
+            if !events.as_array().is_some_and(|events| events.iter().any(|e| e["name"] == event.as_str())) {
+                return Err(format!("no event named '{}'", event));
+            }
+            print_events(&events, format);
+        }
+        Command::History { event, limit } => {
+            let mut url = client.url("/api/v3/history", &[]);
+            url.query_pairs_mut().append_pair("limit", &limit.to_string());
+            if let Some(event) = event {
+                url.query_pairs_mut().append_pair("event", &event);
+            }
+            let entries = client.get(url)?;
+
+            match format {
+                Format::Json => output::json(&entries),
+                Format::Table => output::table(
+                    &["at", "event", "from", "to", "by"],
+                    &output::rows(&entries, &["at", "event", "from", "to", "by"]),
+                ),
+            }
+        }
+        Command::Keys(KeysCommand::Whoami) => print_keys(&client.get_authorized(client.url("/api/v3/whoami", &[]))?, format),
+        Command::Keys(KeysCommand::List) => print_keys(&client.get_authorized(client.url("/api/v3/admin/keys", &[]))?, format),
+        Command::Keys(KeysCommand::Issue { name, scopes, expires_at }) => {
+            let body = serde_json::json!({ "name": name, "scopes": scopes, "expires_at": expires_at });
+            print_issued(&client.post_authorized(client.url("/api/v3/admin/keys", &[]), Some(&body))?, format)
+        }
+        Command::Keys(KeysCommand::Rotate { id, overlap_hours }) => {
+            let mut url = client.url("/api/v3/admin/keys", &[&id, "rotate"]);
+            if let Some(hours) = overlap_hours {
+                url.query_pairs_mut().append_pair("overlap_hours", &hours.to_string());
+            }
+            print_issued(&client.post_authorized(url, None)?, format)
+        }
+        Command::Keys(KeysCommand::Revoke { id }) => {
+            print_keys(&client.delete_authorized(client.url("/api/v3/admin/keys", &[&id]))?, format)
+        }
+        Command::Keys(KeysCommand::Token) => print_tokens(&client.post_authorized(client.url("/api/v3/token", &[]), None)?, format),
+        Command::TwoFactor(TwoFactorCommand::Status) => {
+            let status = client.get_authorized(client.url("/api/v3/2fa", &[]))?;
+            match format {
+                Format::Json => output::json(&status),
+                Format::Table => output::table(
+                    &["enrolled", "confirmed", "backup codes left", "pending", "locked until"],
+                    &output::rows(
+                        &Value::Array(vec![status]),
+                        &["enrolled", "confirmed_at", "backup_codes_left", "pending", "locked_until"],
+                    ),
+                ),
+            }
+        }
+        Command::TwoFactor(TwoFactorCommand::Enroll) => {
+            print_enrollment(&client.post_authorized(client.url("/api/v3/2fa/enroll", &[]), None)?, format)
+        }
+        Command::TwoFactor(TwoFactorCommand::Confirm { code }) => {
+            let body = serde_json::json!({ "code": code });
+            print_backup_codes(&client.post_authorized(client.url("/api/v3/2fa/confirm", &[]), Some(&body))?, format)
+        }
+        Command::TwoFactor(TwoFactorCommand::BackupCodes) => {
+            print_backup_codes(&client.post_authorized(client.url("/api/v3/2fa/backup-codes", &[]), None)?, format)
+        }
+        Command::TwoFactor(TwoFactorCommand::Disable { key_id, user }) => {
+            let mut url = client.url("/api/v3/2fa", &[]);
+            if let Some(key) = key_id {
+                url.query_pairs_mut().append_pair("key", &key);
+            } else if let Some(user) = user {
+                url.query_pairs_mut().append_pair("user", &user);
+            }
+            client.delete_empty(url)?;
+            println!("Second factor removed, admin actions need a new enrolment.");
+        }
+        Command::Users(UsersCommand::List) => print_users(&client.get_authorized(client.url("/api/v3/admin/users", &[]))?, format),
+        Command::Users(UsersCommand::Add { username, scopes }) => {
+            let body = serde_json::json!({ "username": username, "password": prompt_password()?, "scopes": scopes });
+            print_users(&client.post_authorized(client.url("/api/v3/admin/users", &[]), Some(&body))?, format)
+        }
+        Command::Users(UsersCommand::Passwd { id }) => {
+            let body = serde_json::json!({ "password": prompt_password()? });
+            print_users(&client.post_authorized(client.url("/api/v3/admin/users", &[&id, "password"]), Some(&body))?, format)
+        }
+        Command::Users(UsersCommand::Remove { id }) => {
+            print_users(&client.delete_authorized(client.url("/api/v3/admin/users", &[&id]))?, format)
+        }
+        Command::Sessions(SessionsCommand::List) => {
+            print_sessions(&client.get_authorized(client.url("/api/v3/admin/sessions", &[]))?, format)
+        }
+        Command::Sessions(SessionsCommand::End { id }) => {
+            print_sessions(&client.delete_authorized(client.url("/api/v3/sessions", &[&id]))?, format)
+        }
+        Command::Tail { event } => tail(&client, event.as_deref(), format)?,
+        Command::Import { file, apply, yes, prune, tz } => {
+            import::run(&client, &file, prune, tz.as_deref(), apply, yes, format)?
+        }
+        Command::Completions { .. } => unreachable!("handled before connecting"),
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("adharva: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
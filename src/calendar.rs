@@ -0,0 +1,208 @@
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use rocket::http::{ContentType, Status};
+use rocket::{Route, State};
+use sha2::{Digest, Sha256};
+
+use crate::history::{SharedHistory, StatusHistory};
+use crate::schedule::{ScheduleEntry, SharedSchedule};
+use crate::{EventDetail, EventStatus, SharedEvents};
+
+const LINE_LIMIT: usize = 75; // octets per content line before folding, RFC 5545 section 3.1
+
+fn ics_time(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Lowercase ASCII words joined by dashes, only for readability since different names can share one
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// Stable across restarts and status changes, and unique per exact name thanks to the hash, so
+// calendar apps and feed readers update the entry in place. Feeds use it for their ids too
+pub fn event_uid(event_name: &str) -> String {
+    let hash: String = Sha256::digest(event_name.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    match slug(event_name) {
+        slug if slug.is_empty() => hash,
+        slug => format!("{}-{}", slug, hash),
+    }
+}
+
+fn uid(event_name: &str) -> String {
+    format!("{}@adharva", event_uid(event_name))
+}
+
+// Writes one content line, folding it onto continuation lines that start with a space
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > LINE_LIMIT {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn push_event(out: &mut String, event: &EventDetail, entry: &ScheduleEntry, history: &StatusHistory, now: DateTime<Utc>) {
+    let transitions: Vec<_> = history.entries().iter().filter(|h| h.event == event.name).collect();
+    let last_modified = transitions.last().map_or(entry.updated_at, |h| h.at.max(entry.updated_at));
+    let sequence = entry.revision as usize + transitions.len();
+
+    // A delay keeps the original length but moves the slot
+    let (starts_at, ends_at) = match (&event.status, entry.delayed_until) {
+        (EventStatus::Delayed, Some(delayed_until)) => {
+            (delayed_until, delayed_until + (entry.ends_at - entry.starts_at))
+        }
+        _ => (entry.starts_at, entry.ends_at),
+    };
+
+    let (status, summary) = if entry.cancelled {
+        ("CANCELLED", format!("{} (Cancelled)", event.name))
+    } else if event.status == EventStatus::Delayed {
+        ("TENTATIVE", format!("{} (Delayed)", event.name))
+    } else {
+        ("CONFIRMED", event.name.clone())
+    };
+
+    let mut description = format!("Status: {}", if entry.cancelled { "Cancelled" } else { event.status.label() });
+    if event.status == EventStatus::Delayed {
+        if entry.delayed_until.is_some() {
+            write!(description, " (originally {})", entry.starts_at.format("%H:%M UTC")).unwrap();
+        } else {
+            description.push_str(", new time to be announced");
+        }
+    }
+    if let Some(text) = &entry.description {
+        write!(description, "\n\n{}", text).unwrap();
+    }
+
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}", uid(&event.name)));
+    push_line(out, &format!("DTSTAMP:{}", ics_time(now)));
+    push_line(out, &format!("LAST-MODIFIED:{}", ics_time(last_modified)));
+    push_line(out, &format!("SEQUENCE:{}", sequence));
+    push_line(out, &format!("DTSTART:{}", ics_time(starts_at)));
+    push_line(out, &format!("DTEND:{}", ics_time(ends_at)));
+    push_line(out, &format!("SUMMARY:{}", escape(&summary)));
+    push_line(out, &format!("STATUS:{}", status));
+    push_line(out, &format!("DESCRIPTION:{}", escape(&description)));
+    if let Some(venue) = &entry.venue {
+        push_line(out, &format!("LOCATION:{}", escape(venue)));
+    }
+    if let Some(category) = &entry.category {
+        push_line(out, &format!("CATEGORIES:{}", escape(category)));
+    }
+    push_line(out, "END:VEVENT");
+}
+
+fn render(
+    title: &str,
+    events: &SharedEvents,
+    schedule: &SharedSchedule,
+    history: &SharedHistory,
+    include: impl Fn(&EventDetail, &ScheduleEntry) -> bool
+) -> (ContentType, String) {
+    let events = events.lock().unwrap();
+    let schedule = schedule.lock().unwrap();
+    let history = history.lock().unwrap();
+    let now = Utc::now();
+
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//Adharva//Event Server//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape(title)));
+    push_line(&mut out, "REFRESH-INTERVAL;VALUE=DURATION:PT15M");
+    push_line(&mut out, "X-PUBLISHED-TTL:PT15M");
+
+    // Events without schedule metadata have no time slot and are left out
+    for event in events.iter() {
+        if let Some(entry) = schedule.entry(&event.name).filter(|entry| include(event, entry)) {
+            push_event(&mut out, event, entry, &history, now);
+        }
+    }
+    push_line(&mut out, "END:VCALENDAR");
+
+    (ContentType::Calendar.with_params(("charset", "utf-8")), out)
+}
+
+// Lets subscribers use either "tech" or "tech.ics" in the URL
+fn strip_ics(name: &str) -> &str {
+    name.strip_suffix(".ics").unwrap_or(name)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/calendar.ics", tag = "schedule",
+    responses((status = 200, description = "iCalendar feed of the whole fest", content_type = "text/calendar", body = String))
+)]
+#[get("/api/v3/calendar.ics")]
+fn fest_calendar(
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    history: &State<SharedHistory>
+) -> (ContentType, String) {
+    render("Adharva", events, schedule, history, |_, _| true)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/calendar/categories/{category}", tag = "schedule",
+    params(("category" = String, Path, description = "Schedule category, optionally with a .ics suffix")),
+    responses((status = 200, content_type = "text/calendar", body = String))
+)]
+#[get("/api/v3/calendar/categories/<category>")]
+fn category_calendar(
+    category: &str,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    history: &State<SharedHistory>
+) -> (ContentType, String) {
+    let category = strip_ics(category);
+    render(&format!("Adharva – {}", category), events, schedule, history, |_, entry| {
+        entry.category.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(category))
+    })
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/calendar/events/{event_name}", tag = "schedule",
+    params(("event_name" = String, Path, description = "Event name, optionally with a .ics suffix")),
+    responses((status = 200, content_type = "text/calendar", body = String), (status = 404, description = "Unknown or unscheduled event"))
+)]
+#[get("/api/v3/calendar/events/<event_name>")]
+fn event_calendar(
+    event_name: &str,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    history: &State<SharedHistory>
+) -> Result<(ContentType, String), Status> {
+    let event_name = strip_ics(event_name);
+    if schedule.lock().unwrap().entry(event_name).is_none() {
+        return Err(Status::NotFound);
+    }
+
+    Ok(render(&format!("Adharva – {}", event_name), events, schedule, history, |event, _| {
+        event.name == event_name
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![fest_calendar, category_calendar, event_calendar]
+}
@@ -0,0 +1,334 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use dotenvy::var;
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use rocket::http::{ContentType, Status};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+use crate::registration::{Attendance, RegistrationChange, RegistrationState, SharedRegistrations};
+use crate::stream::LiveUpdates;
+use crate::{ApiKey, ApiKeys};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAX_ROUND: u8 = 4;
+
+pub struct CheckinSigner {
+    secret: Vec<u8>,
+}
+
+struct TicketClaims {
+    event: String,
+    registration: String,
+    member: usize,
+}
+
+impl CheckinSigner {
+    // Falls back to the root key so tickets still work on setups without a dedicated secret
+    pub fn load_from_env() -> Self {
+        let secret = var("CHECKIN_SECRET")
+            .or_else(|_| var("API_SECRET_KEY"))
+            .expect("CHECKIN_SECRET not set and no API_SECRET_KEY to fall back to");
+        CheckinSigner { secret: secret.into_bytes() }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+
+    fn issue(&self, event: &str, registration: &str, member: usize) -> String {
+        let payload = format!("{}\n{}\n{}", event, registration, member);
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = mac.finalize().into_bytes();
+
+        format!("{}.{}", URL_SAFE_NO_PAD.encode(payload), URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn verify(&self, token: &str) -> Option<TicketClaims> {
+        let (payload, signature) = token.trim().split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        let mut mac = self.mac();
+        mac.update(&payload);
+        mac.verify_slice(&signature).ok()?;
+
+        let payload = String::from_utf8(payload).ok()?;
+        let mut parts = payload.splitn(3, '\n');
+        Some(TicketClaims {
+            event: parts.next()?.to_string(),
+            registration: parts.next()?.to_string(),
+            member: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Ticket {
+    member: usize,
+    name: String,
+    token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CheckinRequest {
+    token: String,
+    #[serde(default)]
+    round: Option<u8>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CheckinReceipt {
+    event: String,
+    registration: String,
+    team_name: Option<String>,
+    member: String,
+    round: u8,
+    already_checked_in: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AdvanceRequest {
+    round: u8,
+    registrations: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct AdvanceReceipt {
+    round: u8,
+    advanced: Vec<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/register/{event_name}/{id}/tickets", tag = "check-in",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events"), ("id" = String, Path, description = "Registration id")),
+    responses((status = 200, description = "Signed check-in token per team member", body = [Ticket]), (status = 403, description = "Registration is not confirmed"), (status = 404))
+)]
+#[get("/api/v3/register/<event_name>/<id>/tickets")]
+fn tickets(
+    event_name: &str,
+    id: &str,
+    store: &State<SharedRegistrations>,
+    signer: &State<CheckinSigner>
+) -> Result<Json<Vec<Ticket>>, Status> {
+    let store = store.lock().unwrap();
+    let registration = store
+        .event(event_name)
+        .and_then(|event| event.entries.iter().find(|r| r.id == id))
+        .ok_or(Status::NotFound)?;
+
+    if registration.state != RegistrationState::Confirmed {
+        return Err(Status::Forbidden);
+    }
+
+    let tickets = registration
+        .members
+        .iter()
+        .enumerate()
+        .map(|(member, participant)| Ticket {
+            member,
+            name: participant.name.clone(),
+            token: signer.issue(event_name, id, member),
+        })
+        .collect();
+
+    Ok(Json(tickets))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/register/{event_name}/{id}/tickets/{member}", tag = "check-in",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events"), ("id" = String, Path, description = "Registration id"), ("member" = usize, Path, description = "Index of the team member")),
+    responses((status = 200, description = "QR code of the member's check-in token", content_type = "image/svg+xml", body = String), (status = 403), (status = 404))
+)]
+#[get("/api/v3/register/<event_name>/<id>/tickets/<member>")]
+fn ticket_qr(
+    event_name: &str,
+    id: &str,
+    member: usize,
+    store: &State<SharedRegistrations>,
+    signer: &State<CheckinSigner>
+) -> Result<(ContentType, String), Status> {
+    let store = store.lock().unwrap();
+    let registration = store
+        .event(event_name)
+        .and_then(|event| event.entries.iter().find(|r| r.id == id))
+        .ok_or(Status::NotFound)?;
+
+    if registration.state != RegistrationState::Confirmed {
+        return Err(Status::Forbidden);
+    }
+    if member >= registration.members.len() {
+        return Err(Status::NotFound);
+    }
+
+    let token = signer.issue(event_name, id, member);
+    let code = QrCode::new(token.as_bytes()).map_err(|_| Status::InternalServerError)?;
+    let image = code.render::<svg::Color>().min_dimensions(256, 256).build();
+
+    Ok((ContentType::SVG, image))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/checkin", tag = "check-in", request_body = CheckinRequest,
+    responses((status = 200, body = CheckinReceipt), (status = 400, description = "Invalid or tampered token"), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/checkin", data = "<request>")]
+fn checkin(
+    request: Json<CheckinRequest>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    store: &State<SharedRegistrations>,
+    signer: &State<CheckinSigner>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<CheckinReceipt>, Status> {
+    let claims = signer.verify(&request.token).ok_or(Status::BadRequest)?;
+
+    if !api_keys.can_edit(&api_key, &claims.event) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = store.lock().unwrap();
+    let event = store.event_mut(&claims.event).ok_or(Status::NotFound)?;
+    let registration = event
+        .entries
+        .iter_mut()
+        .find(|r| r.id == claims.registration)
+        .ok_or(Status::NotFound)?;
+
+    if registration.state != RegistrationState::Confirmed {
+        return Err(Status::Forbidden);
+    }
+
+    // Without an explicit round, check in to the round the team is currently in
+    let round = request.round.unwrap_or(registration.round);
+    if round == 0 || round > registration.round {
+        return Err(Status::Forbidden);
+    }
+
+    let member = registration.members.get(claims.member).ok_or(Status::NotFound)?.name.clone();
+    let already_checked_in = registration
+        .attendance
+        .iter()
+        .any(|a| a.member == claims.member && a.round == round);
+
+    if !already_checked_in {
+        registration.attendance.push(Attendance {
+            member: claims.member,
+            round,
+            checked_in_at: Utc::now(),
+        });
+    }
+
+    let receipt = CheckinReceipt {
+        event: claims.event.clone(),
+        registration: registration.id.clone(),
+        team_name: registration.team_name.clone(),
+        member,
+        round,
+        already_checked_in,
+    };
+
+    store.save()?;
+    if !already_checked_in {
+        store.publish(updates, &claims.event, RegistrationChange::CheckedIn);
+    }
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/progress/{event_name}/advance", tag = "check-in",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")), request_body = AdvanceRequest,
+    responses((status = 200, body = AdvanceReceipt), (status = 403), (status = 404), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/progress/<event_name>/advance", data = "<request>")]
+fn advance(
+    event_name: &str,
+    request: Json<AdvanceRequest>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    store: &State<SharedRegistrations>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<AdvanceReceipt>, Status> {
+    if !api_keys.can_edit(&api_key, event_name) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let request = request.into_inner();
+    if request.round < 2 || request.round > MAX_ROUND {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let mut store = store.lock().unwrap();
+    let event = store.event_mut(event_name).ok_or(Status::NotFound)?;
+
+    // Only confirmed teams that were in the previous round can move on
+    let previous = request.round - 1;
+    let eligible = |id: &String| {
+        event.entries.iter().any(|r| {
+            r.id == *id && r.state == RegistrationState::Confirmed && r.round >= previous
+        })
+    };
+    if !request.registrations.iter().all(eligible) {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    for registration in event.entries.iter_mut() {
+        if request.registrations.contains(&registration.id) {
+            registration.round = registration.round.max(request.round);
+        }
+    }
+
+    store.save()?;
+    store.publish(updates, event_name, RegistrationChange::Advanced);
+    Ok(Json(AdvanceReceipt { round: request.round, advanced: request.registrations }))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![tickets, ticket_qr, checkin, advance]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(secret: &str) -> CheckinSigner {
+        CheckinSigner { secret: secret.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn issued_tickets_verify_to_their_claims() {
+        let token = signer("checkin-secret").issue("Yukti", "reg123", 2);
+        let claims = signer("checkin-secret").verify(&format!(" {}\n", token)).unwrap();
+
+        assert_eq!((claims.event.as_str(), claims.registration.as_str(), claims.member), ("Yukti", "reg123", 2));
+    }
+
+    #[test]
+    fn forged_or_altered_tickets_are_rejected() {
+        let ours = signer("checkin-secret");
+        let token = ours.issue("Yukti", "reg123", 0);
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // Same claims signed with another secret
+        assert!(ours.verify(&signer("other-secret").issue("Yukti", "reg123", 0)).is_none());
+        // Valid signature moved onto another member's payload
+        let payload = URL_SAFE_NO_PAD.encode("Yukti\nreg123\n1");
+        assert!(ours.verify(&format!("{}.{}", payload, signature)).is_none());
+        // Truncated signature, missing signature and garbage
+        assert!(ours.verify(&token[..token.len() - 2]).is_none());
+        assert!(ours.verify(token.split_once('.').unwrap().0).is_none());
+        assert!(ours.verify("not a ticket").is_none());
+    }
+}
@@ -0,0 +1,407 @@
+use std::sync::Mutex;
+
+use regex::Regex;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method, Status};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Data, Request, Response, Route, State};
+use url::Url;
+use utoipa::ToSchema;
+
+use crate::{persistence, ApiKey, ApiKeys};
+
+const CORS_FILE: &str = "cors.json";
+const REJECTED_PATH: &str = "/api/v3/cors-rejected";
+const DEFAULT_ORIGIN: &str = "https://adharvaa.com";
+const MAX_AGE_SECONDS: u32 = 3600;
+
+const PUBLIC_METHODS: &str = "GET, HEAD, OPTIONS";
+const PUBLIC_HEADERS: &str = "Accept, Content-Type, Last-Event-ID";
+const RESTRICTED_METHODS: &str = "GET, POST, DELETE, OPTIONS";
+const RESTRICTED_HEADERS: &str = "Authorization, Accept, Content-Type, Origin, X-CSRF-Token, X-OTP";
+const EXPOSED_HEADERS: &str = "Content-Disposition, Retry-After, X-OTP-Required";
+
+pub type SharedCors = Mutex<CorsPolicy>;
+
+// The `cors` table of the active Rocket.toml profile, e.g. `[debug.cors]`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct OriginList {
+    #[serde(default)]
+    origins: Vec<String>, // exact origins, scheme://host[:port]
+    #[serde(default)]
+    origin_patterns: Vec<String>, // regexes matched against the whole Origin header
+}
+
+impl Default for OriginList {
+    fn default() -> Self {
+        OriginList { origins: vec![DEFAULT_ORIGIN.to_string()], origin_patterns: Vec::new() }
+    }
+}
+
+impl OriginList {
+    fn contains(&self, origin: &str, pattern: bool) -> bool {
+        let list = if pattern { &self.origin_patterns } else { &self.origins };
+        list.iter().any(|o| o == origin)
+    }
+}
+
+// Browsers send the serialized origin, so configured ones are stored the same way
+fn normalize_origin(origin: &str) -> Option<String> {
+    let url = Url::parse(origin.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.path() != "/" || url.query().is_some() {
+        return None;
+    }
+    url.host()?;
+    Some(url.origin().ascii_serialization())
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+pub struct CorsPolicy {
+    configured: OriginList,
+    runtime: OriginList, // added through the admin API, persisted in cors.json
+    patterns: Vec<Regex>, // compiled configured and runtime patterns
+}
+
+impl CorsPolicy {
+    // Panics on a malformed origin or pattern in Rocket.toml, like any other config error at startup
+    pub fn load() -> Self {
+        let configured: OriginList = rocket::Config::figment().extract_inner("cors").unwrap_or_default();
+        let configured = OriginList {
+            origins: configured
+                .origins
+                .iter()
+                .map(|o| normalize_origin(o).unwrap_or_else(|| panic!("invalid CORS origin in Rocket.toml: {}", o)))
+                .collect(),
+            origin_patterns: configured.origin_patterns,
+        };
+        let runtime = persistence::load(CORS_FILE)
+            .unwrap_or(OriginList { origins: Vec::new(), origin_patterns: Vec::new() });
+
+        let mut policy = CorsPolicy { configured, runtime, patterns: Vec::new() };
+        policy.recompile().expect("invalid CORS origin pattern");
+        policy
+    }
+
+    fn recompile(&mut self) -> Result<(), regex::Error> {
+        self.patterns = self
+            .configured
+            .origin_patterns
+            .iter()
+            .chain(&self.runtime.origin_patterns)
+            .map(|pattern| compile_pattern(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(CORS_FILE, &self.runtime).map_err(|_| Status::InternalServerError)
+    }
+
+    // Only origins added at runtime are kept on disk, the rest come from Rocket.toml
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(CORS_FILE, &self.runtime)
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.configured.origins.iter().chain(&self.runtime.origins).any(|o| o == origin)
+            || self.patterns.iter().any(|pattern| pattern.is_match(origin))
+    }
+
+    fn view(&self) -> CorsView {
+        CorsView { configured: self.configured.clone(), runtime: self.runtime.clone() }
+    }
+}
+
+// Public reads are open to any site, anything carrying credentials or changing state only to allowed origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Policy {
+    Public,
+    Restricted,
+}
+
+impl Policy {
+    fn for_request(method: Method, path: &str, credentials: bool) -> Self {
+        if matches!(method, Method::Get | Method::Head) && !credentials && !path.starts_with("/api/v3/admin") {
+            Policy::Public
+        } else {
+            Policy::Restricted
+        }
+    }
+
+    // A preflight describes the real request in its Access-Control-Request-* headers
+    fn for_preflight(req: &Request<'_>) -> Self {
+        let method = req
+            .headers()
+            .get_one("Access-Control-Request-Method")
+            .and_then(|method| method.parse().ok())
+            .unwrap_or(Method::Get);
+        let credentials = req
+            .headers()
+            .get_one("Access-Control-Request-Headers")
+            .is_some_and(|headers| headers.split(',').any(|h| h.trim().eq_ignore_ascii_case("authorization")));
+        Self::for_request(method, req.uri().path().as_str(), credentials)
+    }
+}
+
+fn is_same_origin(req: &Request<'_>, origin: &str) -> bool {
+    let authority = origin.split_once("://").map_or(origin, |(_, authority)| authority);
+    req.host().is_some_and(|host| host.to_string().eq_ignore_ascii_case(authority))
+}
+
+struct Verdict(Option<Policy>); // None when the request needs no CORS headers
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info { name: "CORS", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let Some(origin) = req.headers().get_one("Origin").map(str::to_string) else {
+            return;
+        };
+        if is_same_origin(req, &origin) {
+            return;
+        }
+
+        let policy = match req.method() {
+            Method::Options => Policy::for_preflight(req),
+            method => Policy::for_request(
+                method,
+                req.uri().path().as_str(),
+                req.headers().contains("Authorization") || req.headers().contains("Cookie"),
+            ),
+        };
+        let allowed = policy == Policy::Public
+            || req.rocket().state::<SharedCors>().is_some_and(|cors| cors.lock().unwrap().allows(&origin));
+
+        if allowed {
+            req.local_cache(|| Verdict(Some(policy)));
+        } else if req.method() != Method::Options {
+            // Refused before the handler runs, a preflight just gets no CORS headers
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(REJECTED_PATH).unwrap());
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(policy) = req.local_cache(|| Verdict(None)).0 else {
+            return;
+        };
+        let origin = req.headers().get_one("Origin").unwrap_or_default();
+
+        match policy {
+            Policy::Public => {
+                res.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+                res.set_header(Header::new("Access-Control-Allow-Methods", PUBLIC_METHODS));
+                res.set_header(Header::new("Access-Control-Allow-Headers", PUBLIC_HEADERS));
+            }
+            Policy::Restricted => {
+                res.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+                res.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+                res.set_header(Header::new("Access-Control-Allow-Methods", RESTRICTED_METHODS));
+                res.set_header(Header::new("Access-Control-Allow-Headers", RESTRICTED_HEADERS));
+                res.adjoin_header(Header::new("Vary", "Origin"));
+            }
+        }
+        res.set_header(Header::new("Access-Control-Expose-Headers", EXPOSED_HEADERS));
+        if req.method() == Method::Options {
+            res.set_header(Header::new("Access-Control-Max-Age", MAX_AGE_SECONDS.to_string()));
+        }
+    }
+}
+
+// Answers every preflight, the fairing decides which CORS headers go on it
+#[options("/<_..>")]
+fn preflight() -> Status {
+    Status::NoContent
+}
+
+#[get("/api/v3/cors-rejected")]
+fn rejected() -> (Status, &'static str) {
+    (Status::Forbidden, "Origin not allowed")
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CorsView {
+    configured: OriginList, // from the active Rocket.toml profile, read-only at runtime
+    runtime: OriginList,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewOrigin {
+    origin: String, // an exact origin, or a regex when `pattern` is set
+    #[serde(default)]
+    pattern: bool,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/cors", tag = "operations",
+    responses((status = 200, description = "Allowed origins for credentialed and write requests", body = CorsView), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/cors")]
+fn get_cors(api_key: ApiKey, api_keys: &State<ApiKeys>, cors: &State<SharedCors>) -> Result<Json<CorsView>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    Ok(Json(cors.lock().unwrap().view()))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/cors/origins", tag = "operations", request_body = NewOrigin,
+    responses((status = 200, description = "Allowed origins after the addition, effective immediately", body = CorsView), (status = 403), (status = 422, description = "Not an origin, or not a valid regex")),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/cors/origins", data = "<request>")]
+fn add_origin(
+    request: Json<NewOrigin>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    cors: &State<SharedCors>
+) -> Result<Json<CorsView>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let request = request.into_inner();
+    let origin = if request.pattern {
+        compile_pattern(&request.origin).map_err(|_| Status::UnprocessableEntity)?;
+        request.origin
+    } else {
+        normalize_origin(&request.origin).ok_or(Status::UnprocessableEntity)?
+    };
+
+    let mut cors = cors.lock().unwrap();
+    if !cors.configured.contains(&origin, request.pattern) && !cors.runtime.contains(&origin, request.pattern) {
+        if request.pattern {
+            cors.runtime.origin_patterns.push(origin);
+            cors.recompile().map_err(|_| Status::UnprocessableEntity)?;
+        } else {
+            cors.runtime.origins.push(origin);
+        }
+        cors.save()?;
+    }
+
+    Ok(Json(cors.view()))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/cors/origins", tag = "operations",
+    params(
+        ("origin" = String, Query, description = "Origin or pattern to remove, as listed"),
+        ("pattern" = Option<bool>, Query, description = "Remove a pattern rather than an exact origin")
+    ),
+    responses((status = 200, body = CorsView), (status = 403), (status = 404), (status = 409, description = "Configured in Rocket.toml, remove it there")),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/cors/origins?<origin>&<pattern>")]
+fn remove_origin(
+    origin: &str,
+    pattern: Option<bool>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    cors: &State<SharedCors>
+) -> Result<Json<CorsView>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let pattern = pattern.unwrap_or(false);
+    let origin = if pattern { origin.to_string() } else { normalize_origin(origin).ok_or(Status::NotFound)? };
+
+    let mut cors = cors.lock().unwrap();
+    if cors.configured.contains(&origin, pattern) {
+        return Err(Status::Conflict);
+    }
+
+    let list = if pattern { &mut cors.runtime.origin_patterns } else { &mut cors.runtime.origins };
+    let index = list.iter().position(|o| *o == origin).ok_or(Status::NotFound)?;
+    list.remove(index);
+    if pattern {
+        cors.recompile().map_err(|_| Status::InternalServerError)?;
+    }
+    cors.save()?;
+
+    Ok(Json(cors.view()))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![preflight, rejected, get_cors, add_origin, remove_origin]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    use super::*;
+
+    fn policy() -> CorsPolicy {
+        let configured = OriginList {
+            origins: vec!["https://adharvaa.com".to_string()],
+            origin_patterns: vec![r"https://[a-z0-9-]+\.adharvaa\.com".to_string()],
+        };
+        let mut policy = CorsPolicy { configured, runtime: OriginList::default(), patterns: Vec::new() };
+        policy.recompile().unwrap();
+        policy
+    }
+
+    #[post("/api/v3/test")]
+    fn write() -> &'static str {
+        "written"
+    }
+
+    #[test]
+    fn origins_are_normalized_like_browsers_send_them() {
+        assert_eq!(normalize_origin(" HTTPS://Adharvaa.COM/ ").as_deref(), Some("https://adharvaa.com"));
+        assert_eq!(normalize_origin("https://adharvaa.com:443").as_deref(), Some("https://adharvaa.com"));
+        assert_eq!(normalize_origin("http://localhost:8080").as_deref(), Some("http://localhost:8080"));
+        assert_eq!(normalize_origin("https://adharvaa.com/events"), None);
+        assert_eq!(normalize_origin("https://adharvaa.com/?a=1"), None);
+        assert_eq!(normalize_origin("ftp://adharvaa.com"), None);
+        assert_eq!(normalize_origin("adharvaa.com"), None);
+    }
+
+    #[test]
+    fn patterns_match_the_whole_origin() {
+        let policy = policy();
+        assert!(policy.allows("https://adharvaa.com"));
+        assert!(policy.allows("https://live.adharvaa.com"));
+        assert!(!policy.allows("https://live.adharvaa.com.evil.example"));
+        assert!(!policy.allows("https://evil.example/?https://live.adharvaa.com"));
+        assert!(!policy.allows("http://live.adharvaa.com"));
+    }
+
+    #[test]
+    fn writes_from_other_origins_are_refused_before_the_handler() {
+        let rocket = rocket::build().manage(Mutex::new(policy())).attach(Cors).mount("/", routes![write, preflight, rejected]);
+        let client = Client::untracked(rocket).unwrap();
+
+        let allowed = client.post("/api/v3/test").header(Header::new("Origin", "https://live.adharvaa.com")).dispatch();
+        assert_eq!(allowed.status(), Status::Ok);
+        assert_eq!(allowed.headers().get_one("Access-Control-Allow-Origin"), Some("https://live.adharvaa.com"));
+        assert_eq!(allowed.into_string().as_deref(), Some("written"));
+
+        let refused = client.post("/api/v3/test").header(Header::new("Origin", "https://evil.example")).dispatch();
+        assert_eq!(refused.status(), Status::Forbidden);
+        assert_eq!(refused.headers().get_one("Access-Control-Allow-Origin"), None);
+
+        let preflight = client
+            .options("/api/v3/test")
+            .header(Header::new("Origin", "https://evil.example"))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .dispatch();
+        assert_eq!(preflight.status(), Status::NoContent);
+        assert_eq!(preflight.headers().get_one("Access-Control-Allow-Origin"), None);
+    }
+}
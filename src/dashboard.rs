@@ -0,0 +1,64 @@
+use rocket::http::ContentType;
+use rocket::response::content::RawHtml;
+use rocket::serde::{json::Json, Serialize};
+use rocket::{Route, State};
+use utoipa::ToSchema;
+
+use crate::{ApiKey, ApiKeys, EventStatus, SharedEvents};
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EditableEvent {
+    name: String,
+    status: EventStatus,
+    label: &'static str,
+    tone: &'static str,
+    transitions: Vec<EventStatus>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/dashboard/events", tag = "events",
+    responses((status = 200, description = "Events the presented key may edit, with suggested next statuses", body = [EditableEvent]), (status = 401)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/dashboard/events")]
+fn editable_events(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>
+) -> Json<Vec<EditableEvent>> {
+    let events = events.lock().unwrap();
+
+    Json(
+        events
+            .iter()
+            .filter(|event| api_keys.can_edit(&api_key, &event.name))
+            .map(|event| EditableEvent {
+                name: event.name.clone(),
+                status: event.status.clone(),
+                label: event.status.label(),
+                tone: event.status.tone(),
+                transitions: event.status.transitions(),
+            })
+            .collect(),
+    )
+}
+
+#[get("/admin")]
+fn index() -> RawHtml<&'static str> {
+    RawHtml(include_str!("../static/dashboard/index.html"))
+}
+
+#[get("/admin/app.js")]
+fn script() -> (ContentType, &'static str) {
+    (ContentType::JavaScript, include_str!("../static/dashboard/app.js"))
+}
+
+#[get("/admin/app.css")]
+fn stylesheet() -> (ContentType, &'static str) {
+    (ContentType::CSS, include_str!("../static/dashboard/app.css"))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![editable_events, index, script, stylesheet]
+}
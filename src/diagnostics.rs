@@ -0,0 +1,134 @@
+use std::fs::OpenOptions;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Serialize};
+use rocket::{Route, State};
+use utoipa::ToSchema;
+
+use crate::{persistence, ApiKey, ApiKeys, SharedEvents, STATE_FILE};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ConfigSource {
+    state_file: String,
+    env_file: Option<String>,
+    rocket_profile: String,
+    rocket_sources: Vec<String>,
+}
+
+pub struct StartupReport {
+    started_at: DateTime<Utc>,
+    started: Instant,
+    config: ConfigSource,
+    warnings: Vec<String>,
+}
+
+impl StartupReport {
+    pub fn new(state_file: &str) -> Self {
+        let figment = rocket::Config::figment();
+
+        StartupReport {
+            started_at: Utc::now(),
+            started: Instant::now(),
+            config: ConfigSource {
+                state_file: state_file.to_string(),
+                env_file: dotenvy::dotenv().ok().map(|path| path.display().to_string()),
+                rocket_profile: figment.profile().to_string(),
+                rocket_sources: figment.metadata().map(|m| m.name.to_string()).collect(),
+            },
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn warn(&mut self, warning: &str) {
+        self.warnings.push(warning.to_string());
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ReadinessChecks {
+    state_loaded: bool,
+    state_writable: bool,
+    keys_configured: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Readiness {
+    ready: bool,
+    checks: ReadinessChecks,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DiagnosticsReport {
+    version: &'static str,
+    started_at: DateTime<Utc>,
+    uptime_seconds: u64,
+    config: ConfigSource,
+    last_persisted_at: Option<DateTime<Utc>>,
+    warnings: Vec<String>,
+}
+
+#[utoipa::path(
+    get, path = "/healthz", tag = "operations",
+    responses((status = 200, description = "The process is alive", body = String))
+)]
+#[get("/healthz")]
+fn healthz() -> &'static str {
+    "ok"
+}
+
+#[utoipa::path(
+    get, path = "/readyz", tag = "operations",
+    responses((status = 200, body = Readiness), (status = 503, body = Readiness))
+)]
+#[get("/readyz")]
+fn readyz(events: &State<SharedEvents>, api_keys: &State<ApiKeys>) -> (Status, Json<Readiness>) {
+    let checks = ReadinessChecks {
+        state_loaded: events.lock().is_ok_and(|events| !events.is_empty()),
+        // Opening for append checks permissions without touching the contents
+        state_writable: OpenOptions::new().append(true).open(STATE_FILE).is_ok(),
+        keys_configured: api_keys.has_admin(),
+    };
+    let ready = checks.state_loaded && checks.state_writable && checks.keys_configured;
+    let status = if ready { Status::Ok } else { Status::ServiceUnavailable };
+
+    (status, Json(Readiness { ready, checks }))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/diagnostics", tag = "operations",
+    responses((status = 200, body = DiagnosticsReport), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/diagnostics")]
+fn diagnostics(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    report: &State<StartupReport>
+) -> Result<Json<DiagnosticsReport>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    Ok(Json(DiagnosticsReport {
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: report.started_at,
+        uptime_seconds: report.started.elapsed().as_secs(),
+        config: report.config.clone(),
+        last_persisted_at: persistence::last_write(),
+        warnings: report.warnings.clone(),
+    }))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![healthz, readyz, diagnostics]
+}
@@ -0,0 +1,287 @@
+use std::sync::Mutex;
+
+use askama::Template;
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::Status;
+use rocket::response::content::RawHtml;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use utoipa::ToSchema;
+
+use crate::announcements::{Announcement, SharedAnnouncements};
+use crate::emergency::SharedEmergency;
+use crate::schedule::{ScheduleEntry, SharedSchedule};
+use crate::{persistence, ApiKey, ApiKeys, EventStatus, SharedEvents};
+
+const DISPLAYS_FILE: &str = "displays.json";
+const DEFAULT_ROTATE_SECONDS: u32 = 10;
+
+pub type SharedDisplays = Mutex<DisplayBoards>;
+
+fn default_rotate_seconds() -> u32 {
+    DEFAULT_ROTATE_SECONDS
+}
+
+// A venue screen. Its token only unlocks the read-only feed, it is never accepted as an ApiKey
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Display {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub events: Vec<String>, // events shown on this screen, empty means every event
+    #[serde(default)]
+    pub venue: Option<String>, // only events scheduled at this venue, on top of `events`
+    #[serde(default = "default_rotate_seconds")]
+    pub rotate_seconds: u32,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Display {
+    fn shows(&self, event_name: &str, entry: Option<&ScheduleEntry>) -> bool {
+        let listed = self.events.is_empty() || self.events.iter().any(|e| e == event_name);
+        let at_venue = self.venue.as_deref().is_none_or(|venue| {
+            entry.and_then(|entry| entry.venue.as_deref()).is_some_and(|at| at.trim().eq_ignore_ascii_case(venue))
+        });
+        listed && at_venue
+    }
+}
+
+// When the event is expected to start, taking a known delay into account
+fn expected_start(status: &EventStatus, entry: &ScheduleEntry) -> DateTime<Utc> {
+    match (status, entry.delayed_until) {
+        (EventStatus::Delayed, Some(delayed_until)) => delayed_until,
+        _ => entry.starts_at,
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct DisplayBoards {
+    displays: Vec<Display>,
+}
+
+impl DisplayBoards {
+    pub fn load() -> Self {
+        persistence::load(DISPLAYS_FILE).unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(DISPLAYS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(DISPLAYS_FILE, self)
+    }
+
+    fn by_token(&self, token: &str) -> Option<&Display> {
+        self.displays.iter().find(|d| d.token == token)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewDisplay {
+    name: String,
+    #[serde(default)]
+    events: Vec<String>,
+    venue: Option<String>,
+    rotate_seconds: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DisplayEvent {
+    name: String,
+    status: EventStatus,
+    label: &'static str,
+    tone: &'static str,
+    venue: Option<String>,
+    starts_at: Option<DateTime<Utc>>, // from the schedule, moved by a known delay
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DisplayFeed {
+    name: String,
+    rotate_seconds: u32,
+    emergency: Option<String>,
+    now: Vec<DisplayEvent>,  // running events
+    next: Vec<DisplayEvent>, // upcoming and delayed events by expected start, ended ones are left off the screen
+    announcements: Vec<Announcement>,
+}
+
+#[derive(Template)]
+#[template(path = "display.html")]
+struct DisplayPage<'a> {
+    name: &'a str,
+    token: &'a str,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/display/{token}", tag = "display",
+    params(("token" = String, Path, description = "Display token issued by an admin")),
+    responses((status = 200, body = DisplayFeed), (status = 404, description = "Unknown display token"))
+)]
+#[get("/api/v3/display/<token>")]
+fn display_feed(
+    token: &str,
+    displays: &State<SharedDisplays>,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    announcements: &State<SharedAnnouncements>,
+    emergency: &State<SharedEmergency>
+) -> Result<Json<DisplayFeed>, Status> {
+    let display = displays.lock().unwrap().by_token(token).cloned().ok_or(Status::NotFound)?;
+
+    // Lock order as documented on SharedEvents
+    let emergency = emergency.lock().unwrap().as_ref().map(|e| e.message.clone());
+    let events = events.lock().unwrap();
+    let schedule = schedule.lock().unwrap();
+
+    let (mut now, mut next, mut shown) = (Vec::new(), Vec::new(), Vec::new());
+    for event in events.iter() {
+        let scheduled = schedule.entry(&event.name);
+        if !display.shows(&event.name, scheduled) {
+            continue;
+        }
+        shown.push(event.name.clone());
+
+        let entry = DisplayEvent {
+            name: event.name.clone(),
+            status: event.status.clone(),
+            label: event.status.label(),
+            tone: event.status.tone(),
+            venue: scheduled.and_then(|entry| entry.venue.clone()),
+            starts_at: scheduled.map(|entry| expected_start(&event.status, entry)),
+        };
+        match event.status {
+            EventStatus::Ended => (),
+            EventStatus::Soon | EventStatus::Delayed => next.push(entry),
+            _ => now.push(entry),
+        }
+    }
+    drop((schedule, events));
+    // Unscheduled events go last, in lineup order
+    next.sort_by_key(|entry| (entry.starts_at.is_none(), entry.starts_at));
+
+    let announcements = announcements
+        .lock()
+        .unwrap()
+        .active()
+        .filter(|a| a.events.is_empty() || shown.iter().any(|e| a.concerns(e)))
+        .cloned()
+        .collect();
+
+    Ok(Json(DisplayFeed {
+        name: display.name,
+        rotate_seconds: display.rotate_seconds,
+        emergency,
+        now,
+        next,
+        announcements,
+    }))
+}
+
+#[get("/display/<token>")]
+fn display_page(token: &str, displays: &State<SharedDisplays>) -> Result<RawHtml<String>, Status> {
+    let displays = displays.lock().unwrap();
+    let display = displays.by_token(token).ok_or(Status::NotFound)?;
+
+    DisplayPage { name: &display.name, token: &display.token }
+        .render()
+        .map(RawHtml)
+        .map_err(|_| Status::InternalServerError)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/displays", tag = "display",
+    responses((status = 200, body = [Display]), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/displays")]
+fn list_displays(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    displays: &State<SharedDisplays>
+) -> Result<Json<Vec<Display>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    Ok(Json(displays.lock().unwrap().displays.clone()))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/displays", tag = "display", request_body = NewDisplay,
+    responses((status = 200, description = "The new display, open /display/{token} on the screen", body = Display), (status = 403), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/displays", data = "<display>")]
+fn create_display(
+    display: Json<NewDisplay>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    displays: &State<SharedDisplays>,
+    events: &State<SharedEvents>
+) -> Result<Json<Display>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let display = display.into_inner();
+    let known = events.lock().unwrap().iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+    if display.name.trim().is_empty()
+        || display.venue.as_deref().is_some_and(|venue| venue.trim().is_empty())
+        || display.rotate_seconds == Some(0)
+        || !display.events.iter().all(|e| known.contains(e))
+    {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let created = Display {
+        id: rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect(),
+        name: display.name.trim().to_string(),
+        events: display.events,
+        venue: display.venue.map(|venue| venue.trim().to_string()),
+        rotate_seconds: display.rotate_seconds.unwrap_or(DEFAULT_ROTATE_SECONDS),
+        token: rand::thread_rng().sample_iter(&Alphanumeric).take(32).map(char::from).collect(),
+        created_at: Utc::now(),
+    };
+
+    let mut displays = displays.lock().unwrap();
+    displays.displays.push(created.clone());
+    displays.save()?;
+
+    Ok(Json(created))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/displays/{id}", tag = "display",
+    params(("id" = String, Path, description = "Display id")),
+    responses((status = 200, description = "The removed display, its token stops working", body = Display), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/displays/<id>")]
+fn delete_display(
+    id: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    displays: &State<SharedDisplays>
+) -> Result<Json<Display>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut displays = displays.lock().unwrap();
+    let index = displays.displays.iter().position(|d| d.id == id).ok_or(Status::NotFound)?;
+    let removed = displays.displays.remove(index);
+    displays.save()?;
+
+    Ok(Json(removed))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![display_feed, display_page, list_displays, create_display, delete_display]
+}
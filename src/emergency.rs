@@ -0,0 +1,201 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::history::SharedHistory;
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{persistence, ApiKey, ApiKeys, EventDetail, EventStatus, RateLimitGuard, SharedEvents, STATE_FILE};
+
+const EMERGENCY_FILE: &str = "emergency.json";
+
+pub type SharedEmergency = Mutex<Option<Emergency>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Emergency {
+    pub message: String,
+    pub forced_status: Option<EventStatus>,
+    pub declared_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    prior: Vec<EventDetail>, // statuses to restore once the emergency is cleared
+}
+
+// Kept on disk so a restart mid-emergency can still restore the original statuses
+pub fn load() -> Option<Emergency> {
+    persistence::load(EMERGENCY_FILE)
+}
+
+pub fn flush(emergency: &Option<Emergency>) -> std::io::Result<()> {
+    persistence::save(EMERGENCY_FILE, emergency)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EmergencyRequest {
+    message: String,
+    status: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EmergencyNotice {
+    message: String,
+    forced_status: Option<EventStatus>,
+    declared_at: DateTime<Utc>,
+}
+
+impl From<&Emergency> for EmergencyNotice {
+    fn from(emergency: &Emergency) -> Self {
+        EmergencyNotice {
+            message: emergency.message.clone(),
+            forced_status: emergency.forced_status.clone(),
+            declared_at: emergency.declared_at,
+        }
+    }
+}
+
+fn save_events(events: &[EventDetail]) -> Result<(), Status> {
+    persistence::save(STATE_FILE, events).map_err(|_| Status::InternalServerError)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/emergency", tag = "emergency",
+    responses((status = 200, body = EmergencyNotice), (status = 404, description = "No emergency in effect"))
+)]
+#[get("/api/v3/emergency")]
+fn current_emergency(
+    emergency: &State<SharedEmergency>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Option<Json<EmergencyNotice>> {
+    emergency.lock().unwrap().as_ref().map(|e| Json(e.into()))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/emergency", tag = "emergency", request_body = EmergencyRequest,
+    responses((status = 200, body = EmergencyNotice), (status = 403), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/emergency", data = "<request>")]
+fn declare_emergency(
+    request: Json<EmergencyRequest>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    emergency: &State<SharedEmergency>,
+    state: &State<SharedEvents>,
+    history: &State<SharedHistory>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<EmergencyNotice>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let request = request.into_inner();
+    if request.message.trim().is_empty() {
+        return Err(Status::UnprocessableEntity);
+    }
+    let forced_status = match request.status {
+        Some(status) => Some(EventStatus::from_str(&status).map_err(|_| Status::UnprocessableEntity)?),
+        None => None,
+    };
+
+    let mut emergency = emergency.lock().unwrap();
+    let mut events = state.lock().unwrap();
+
+    // Re-declaring only swaps the message and status, the original snapshot is kept
+    let prior = match emergency.as_ref() {
+        Some(previous) => previous.prior.clone(),
+        None => events.clone(),
+    };
+
+    let mut forced = events.clone();
+    for event in forced.iter_mut() {
+        let original = prior.iter().find(|e| e.name == event.name).map(|e| e.status.clone());
+        if let Some(status) = forced_status.clone().or(original) {
+            event.status = status;
+        }
+    }
+
+    let declared = Emergency {
+        message: request.message.trim().to_string(),
+        forced_status,
+        declared_at: Utc::now(),
+        prior,
+    };
+    // Memory only changes once both files are written, so a failed save never loses the snapshot
+    persistence::save(EMERGENCY_FILE, &declared).map_err(|_| Status::InternalServerError)?;
+    save_events(&forced)?;
+
+    let notice = EmergencyNotice::from(&declared);
+    *emergency = Some(declared);
+    let before = std::mem::replace(&mut *events, forced);
+
+    updates.publish(LiveUpdate::Emergency { message: Some(notice.message.clone()) });
+    let changes = LiveUpdate::status_changes(&before, &events, &api_keys.identity(&api_key));
+    if let Err(error) = history.lock().unwrap().record(&changes) {
+        warn!(%error, "failed to record status history");
+    }
+
+    updates.publish(LiveUpdate::Events { events: events.clone() });
+    for change in changes {
+        updates.publish(change);
+    }
+    Ok(Json(notice))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/emergency", tag = "emergency",
+    responses((status = 200, description = "Events with their restored statuses", body = [EventDetail]), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/emergency")]
+fn clear_emergency(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    emergency: &State<SharedEmergency>,
+    state: &State<SharedEvents>,
+    history: &State<SharedHistory>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<Vec<EventDetail>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut emergency = emergency.lock().unwrap();
+    let cleared = emergency.as_ref().ok_or(Status::NotFound)?;
+    let mut events = state.lock().unwrap();
+
+    let mut restored = events.clone();
+    for event in restored.iter_mut() {
+        if let Some(original) = cleared.prior.iter().find(|e| e.name == event.name) {
+            event.status = original.status.clone();
+        }
+    }
+
+    save_events(&restored)?;
+    persistence::save(EMERGENCY_FILE, &None::<Emergency>).map_err(|_| Status::InternalServerError)?;
+    *emergency = None;
+    let before = std::mem::replace(&mut *events, restored);
+
+    updates.publish(LiveUpdate::Emergency { message: None });
+    let changes = LiveUpdate::status_changes(&before, &events, &api_keys.identity(&api_key));
+    if let Err(error) = history.lock().unwrap().record(&changes) {
+        warn!(%error, "failed to record status history");
+    }
+
+    updates.publish(LiveUpdate::Events { events: events.clone() });
+    for change in changes {
+        updates.publish(change);
+    }
+    Ok(Json(events.clone()))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![current_emergency, declare_emergency, clear_emergency]
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use dotenvy::var;
+use rocket::http::{ContentType, Header, Status};
+use rocket::{Route, State};
+use rust_xlsxwriter::{Format as CellFormat, Workbook};
+
+use crate::audit::SharedAudit;
+use crate::history::SharedHistory;
+use crate::registration::SharedRegistrations;
+use crate::schedule::SharedSchedule;
+use crate::{ApiKey, ApiKeys, SharedEvents};
+
+enum Cell {
+    Text(String),
+    Number(i64),
+    Time(DateTime<Utc>),
+    Empty,
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+impl From<Option<String>> for Cell {
+    fn from(text: Option<String>) -> Self {
+        text.map_or(Cell::Empty, Cell::Text)
+    }
+}
+
+impl From<DateTime<Utc>> for Cell {
+    fn from(at: DateTime<Utc>) -> Self {
+        Cell::Time(at)
+    }
+}
+
+struct Table {
+    name: &'static str,
+    headers: &'static [&'static str],
+    rows: Vec<Vec<Cell>>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Xlsx,
+}
+
+// EXPORT_TIMEZONE, also used to read local times in imports
+pub fn default_timezone() -> Tz {
+    var("EXPORT_TIMEZONE").ok().and_then(|tz| tz.parse().ok()).unwrap_or(Tz::UTC)
+}
+
+// Optional narrowing applied to whichever timestamp a dataset is keyed on
+struct Filters<'a> {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    event: Option<&'a str>,
+    tz: Tz,
+}
+
+impl Filters<'_> {
+    fn in_range(&self, at: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| at >= from) && self.to.is_none_or(|to| at < to)
+    }
+
+    fn wants(&self, event_name: &str) -> bool {
+        self.event.is_none_or(|event| event == event_name)
+    }
+}
+
+// Accepts RFC 3339 timestamps or plain dates, which cover the whole day in the export time zone
+fn parse_bound(value: &str, tz: Tz, end_of_range: bool) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let date = if end_of_range { date.succ_opt()? } else { date };
+    let midnight = tz.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest()?;
+    Some(midnight.with_timezone(&Utc))
+}
+
+fn format_duration(seconds: i64) -> String {
+    let (hours, minutes) = (seconds / 3600, (seconds % 3600) / 60);
+    format!("{}h {:02}m", hours, minutes)
+}
+
+fn events_table(
+    filters: &Filters,
+    events: &SharedEvents,
+    schedule: &SharedSchedule,
+    history: &SharedHistory,
+    registrations: &SharedRegistrations
+) -> Table {
+    // Lock order as documented on SharedEvents
+    let events = events.lock().unwrap();
+    let schedule = schedule.lock().unwrap();
+    let history = history.lock().unwrap();
+    let registrations = registrations.lock().unwrap();
+
+    let rows = events
+        .iter()
+        .filter(|event| filters.wants(&event.name))
+        .map(|event| {
+            let entry = schedule.entry(&event.name);
+            let counts = registrations.counts(&event.name);
+            let last_changed = history.entries().iter().rev().find(|h| h.event == event.name);
+
+            vec![
+                event.name.as_str().into(),
+                event.status.label().into(),
+                entry.and_then(|e| e.category.clone()).into(),
+                entry.and_then(|e| e.venue.clone()).into(),
+                entry.map_or(Cell::Empty, |e| e.starts_at.into()),
+                entry.map_or(Cell::Empty, |e| e.ends_at.into()),
+                last_changed.map_or(Cell::Empty, |h| h.at.into()),
+                counts.as_ref().map_or(Cell::Empty, |c| Cell::Number(c.registered as i64)),
+                counts.as_ref().map_or(Cell::Empty, |c| Cell::Number(c.checked_in as i64)),
+            ]
+        })
+        .collect();
+
+    Table {
+        name: "events",
+        headers: &["event", "status", "category", "venue", "starts_at", "ends_at", "last_changed_at", "registered", "checked_in"],
+        rows,
+    }
+}
+
+// Each transition's duration is how long the event stayed in the new status, up to now for the current one
+fn history_table(filters: &Filters, history: &SharedHistory) -> Table {
+    let history = history.lock().unwrap();
+    let entries = history.entries();
+    let now = Utc::now();
+
+    // Walked newest first so the following transition of each event is already known
+    let mut next_at: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    let mut rows = Vec::new();
+    for entry in entries.iter().rev() {
+        let next = next_at.insert(entry.event.as_str(), entry.at);
+        if !filters.wants(&entry.event) || !filters.in_range(entry.at) {
+            continue;
+        }
+        let seconds = (next.unwrap_or(now) - entry.at).num_seconds();
+
+        rows.push(vec![
+            entry.at.into(),
+            entry.event.as_str().into(),
+            entry.change.label().into(),
+            entry.from.label().into(),
+            entry.to.label().into(),
+            entry.by.as_str().into(),
+            Cell::Number(seconds),
+            format_duration(seconds).into(),
+            if next.is_none() { "yes" } else { "no" }.into(),
+        ]);
+    }
+    rows.reverse();
+
+    Table {
+        name: "history",
+        headers: &["at", "event", "change", "from", "to", "by", "duration_seconds", "duration", "current"],
+        rows,
+    }
+}
+
+// Audit paths contain the event name for event routes, which is what the event filter matches on
+fn audit_table(filters: &Filters, audit: &SharedAudit) -> Table {
+    let audit = audit.lock().unwrap();
+
+    let rows = audit
+        .entries()
+        .iter()
+        .filter(|entry| filters.in_range(entry.at))
+        .filter(|entry| filters.event.is_none_or(|event| entry.path.split('/').any(|segment| segment == event)))
+        .map(|entry| {
+            vec![
+                entry.at.into(),
+                entry.request_id.as_str().into(),
+                entry.actor.clone().into(),
+                entry.method.as_str().into(),
+                entry.path.as_str().into(),
+                Cell::Number(entry.status as i64),
+            ]
+        })
+        .collect();
+
+    Table { name: "audit", headers: &["at", "request_id", "actor", "method", "path", "status"], rows }
+}
+
+// One row per team member so contact details stay in their own columns
+fn registrations_table(filters: &Filters, events: &SharedEvents, registrations: &SharedRegistrations) -> Table {
+    let events = events.lock().unwrap();
+    let registrations = registrations.lock().unwrap();
+    let mut rows = Vec::new();
+
+    for event in events.iter().filter(|event| filters.wants(&event.name)) {
+        let Some(event_registrations) = registrations.event(&event.name) else {
+            continue;
+        };
+
+        for registration in event_registrations.entries.iter().filter(|r| filters.in_range(r.registered_at)) {
+            for (index, member) in registration.members.iter().enumerate() {
+                let rounds: Vec<String> = registration
+                    .attendance
+                    .iter()
+                    .filter(|a| a.member == index)
+                    .map(|a| a.round.to_string())
+                    .collect();
+
+                rows.push(vec![
+                    event.name.as_str().into(),
+                    registration.id.as_str().into(),
+                    registration.team_name.clone().into(),
+                    format!("{:?}", registration.state).into(),
+                    Cell::Number(registration.round as i64),
+                    member.name.as_str().into(),
+                    member.email.as_str().into(),
+                    member.phone.as_str().into(),
+                    registration.registered_at.into(),
+                    rounds.join(", ").into(),
+                ]);
+            }
+        }
+    }
+
+    Table {
+        name: "registrations",
+        headers: &["event", "registration", "team_name", "state", "round", "member", "email", "phone", "registered_at", "checked_in_rounds"],
+        rows,
+    }
+}
+
+// Spreadsheet apps evaluate CSV fields that look like formulas, and registrations are free text from the
+// public form, so those fields get a leading apostrophe to keep them text
+fn csv_text(text: &str) -> String {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn to_csv(table: &Table, tz: Tz) -> Result<Vec<u8>, Status> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(table.headers).map_err(|_| Status::InternalServerError)?;
+
+    for row in &table.rows {
+        let record = row.iter().map(|cell| match cell {
+            Cell::Text(text) => csv_text(text),
+            Cell::Number(number) => number.to_string(),
+            Cell::Time(at) => at.with_timezone(&tz).to_rfc3339_opts(SecondsFormat::Secs, false),
+            Cell::Empty => String::new(),
+        });
+        writer.write_record(record).map_err(|_| Status::InternalServerError)?;
+    }
+
+    writer.into_inner().map_err(|_| Status::InternalServerError)
+}
+
+fn to_xlsx(table: &Table, tz: Tz) -> Result<Vec<u8>, Status> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(table.name).map_err(|_| Status::InternalServerError)?;
+
+    let bold = CellFormat::new().set_bold();
+    let time = CellFormat::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    for (col, header) in table.headers.iter().enumerate() {
+        sheet.write_with_format(0, col as u16, *header, &bold).map_err(|_| Status::InternalServerError)?;
+    }
+
+    for (row, cells) in table.rows.iter().enumerate() {
+        let row = row as u32 + 1;
+        for (col, cell) in cells.iter().enumerate() {
+            let col = col as u16;
+            let written = match cell {
+                // Always a string cell, never a formula, whatever the text starts with
+                Cell::Text(text) => sheet.write_string(row, col, text.as_str()).map(|_| ()),
+                Cell::Number(number) => sheet.write(row, col, *number as f64).map(|_| ()),
+                // Spreadsheets have no time zones, so times are written as wall-clock time in the export zone
+                Cell::Time(at) => sheet
+                    .write_datetime_with_format(row, col, at.with_timezone(&tz).naive_local(), &time)
+                    .map(|_| ()),
+                Cell::Empty => Ok(()),
+            };
+            written.map_err(|_| Status::InternalServerError)?;
+        }
+    }
+    sheet.autofit();
+
+    workbook.save_to_buffer().map_err(|_| Status::InternalServerError)
+}
+
+#[derive(Responder)]
+pub struct Download {
+    body: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/export/{file}", tag = "operations",
+    params(
+        ("file" = String, Path, description = "events, history, audit or registrations, followed by .csv or .xlsx"),
+        ("from" = Option<String>, Query, description = "Start of the range, RFC 3339 or YYYY-MM-DD"),
+        ("to" = Option<String>, Query, description = "End of the range, RFC 3339 or YYYY-MM-DD (inclusive day)"),
+        ("event" = Option<String>, Query, description = "Only rows for this event"),
+        ("tz" = Option<String>, Query, description = "IANA time zone for timestamps, e.g. Asia/Kolkata, default EXPORT_TIMEZONE or UTC")
+    ),
+    responses((status = 200, description = "The dataset as a download", body = Vec<u8>), (status = 403), (status = 404), (status = 422, description = "Unknown time zone or unparseable date")),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/export/<file>?<from>&<to>&<event>&<tz>")]
+#[allow(clippy::too_many_arguments)]
+fn export(
+    file: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    event: Option<&str>,
+    tz: Option<&str>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    history: &State<SharedHistory>,
+    audit: &State<SharedAudit>,
+    registrations: &State<SharedRegistrations>
+) -> Result<Download, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let (dataset, extension) = file.rsplit_once('.').ok_or(Status::NotFound)?;
+    let format = match extension {
+        "csv" => Format::Csv,
+        "xlsx" => Format::Xlsx,
+        _ => return Err(Status::NotFound),
+    };
+
+    let tz = match tz {
+        Some(tz) => tz.parse().map_err(|_| Status::UnprocessableEntity)?,
+        None => default_timezone(),
+    };
+    let filters = Filters {
+        from: from.map(|from| parse_bound(from, tz, false).ok_or(Status::UnprocessableEntity)).transpose()?,
+        to: to.map(|to| parse_bound(to, tz, true).ok_or(Status::UnprocessableEntity)).transpose()?,
+        event,
+        tz,
+    };
+
+    let table = match dataset {
+        "events" => events_table(&filters, events, schedule, history, registrations),
+        "history" => history_table(&filters, history),
+        "audit" => audit_table(&filters, audit),
+        "registrations" => registrations_table(&filters, events, registrations),
+        _ => return Err(Status::NotFound),
+    };
+
+    let (body, content_type) = match format {
+        Format::Csv => (to_csv(&table, filters.tz)?, ContentType::CSV.with_params(("charset", "utf-8"))),
+        Format::Xlsx => (
+            to_xlsx(&table, filters.tz)?,
+            ContentType::new("application", "vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        ),
+    };
+    let filename = format!("adharva-{}-{}.{}", table.name, Utc::now().with_timezone(&tz).format("%Y%m%d-%H%M"), extension);
+
+    Ok(Download {
+        body,
+        content_type,
+        disposition: Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", filename)),
+    })
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![export]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOLKATA: Tz = chrono_tz::Asia::Kolkata;
+
+    #[test]
+    fn csv_fields_that_look_like_formulas_stay_text() {
+        let table = Table {
+            name: "registrations",
+            headers: &["team_name", "phone", "registered_at", "round"],
+            rows: vec![
+                vec!["=HYPERLINK(\"http://x\")".into(), "+91 98450 00000".into(), Utc.timestamp_opt(0, 0).unwrap().into(), Cell::Number(2)],
+                vec!["@SUM(A1)".into(), "-1".into(), Cell::Empty, Cell::Number(-1)],
+                vec!["Team Rocket".into(), "\tcmd".into(), Cell::Empty, Cell::Empty],
+            ],
+        };
+        let csv = String::from_utf8(to_csv(&table, KOLKATA).unwrap()).unwrap();
+
+        assert_eq!(
+            csv,
+            "team_name,phone,registered_at,round\n\
+             \"'=HYPERLINK(\"\"http://x\"\")\",'+91 98450 00000,1970-01-01T05:30:00+05:30,2\n\
+             '@SUM(A1),'-1,,-1\n\
+             Team Rocket,'\tcmd,,\n"
+        );
+    }
+
+    #[test]
+    fn date_bounds_cover_whole_days_in_the_export_zone() {
+        let from = parse_bound("2026-03-01", KOLKATA, false).unwrap();
+        let to = parse_bound("2026-03-01", KOLKATA, true).unwrap();
+        assert_eq!(from.to_rfc3339(), "2026-02-28T18:30:00+00:00");
+        assert_eq!(to.to_rfc3339(), "2026-03-01T18:30:00+00:00");
+
+        let filters = Filters { from: Some(from), to: Some(to), event: None, tz: KOLKATA };
+        assert!(filters.in_range(from));
+        assert!(!filters.in_range(to));
+        assert_eq!(parse_bound("2026-03-01T10:00:00Z", KOLKATA, true).unwrap().to_rfc3339(), "2026-03-01T10:00:00+00:00");
+        assert!(parse_bound("01/03/2026", KOLKATA, false).is_none());
+    }
+}
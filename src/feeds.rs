@@ -0,0 +1,246 @@
+use std::cmp::Reverse;
+
+use chrono::{DateTime, Utc};
+use dotenvy::var;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Route, State};
+
+use crate::announcements::SharedAnnouncements;
+use crate::calendar;
+use crate::history::{HistoryChange, SharedHistory};
+use crate::SharedEvents;
+
+const FEED_LIMIT: usize = 50;
+const TAG_AUTHORITY: &str = "tag:adharvaa.com,2025";
+
+// Absolute base for links, PUBLIC_URL when set, otherwise derived from the Host header
+pub struct BaseUrl(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BaseUrl {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let base = var("PUBLIC_URL").ok().unwrap_or_else(|| {
+            let host = req.host().map(|host| host.to_string()).unwrap_or_else(|| "localhost".to_string());
+            format!("http://{}", host)
+        });
+        Outcome::Success(BaseUrl(base.trim_end_matches('/').to_string()))
+    }
+}
+
+struct Item {
+    id: String,
+    title: String,
+    summary: String,
+    at: DateTime<Utc>,
+}
+
+struct Feed {
+    title: String,
+    self_path: String,
+    items: Vec<Item>, // newest first
+}
+
+enum Format {
+    Atom,
+    Rss,
+}
+
+impl Format {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "atom" => Some(Format::Atom),
+            "rss" => Some(Format::Rss),
+            _ => None,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Status transitions and announcements for one event, or the whole fest when `event` is None
+fn collect(
+    title: String,
+    self_path: String,
+    event: Option<&str>,
+    history: &SharedHistory,
+    announcements: &SharedAnnouncements
+) -> Feed {
+    let mut items: Vec<Item> = history
+        .lock()
+        .unwrap()
+        .entries()
+        .iter()
+        .rev()
+        .filter(|entry| event.is_none_or(|event| entry.event == event))
+        .take(FEED_LIMIT)
+        .map(|entry| Item {
+            id: format!("{}:status/{}/{}", TAG_AUTHORITY, calendar::event_uid(&entry.event), entry.at.timestamp_millis()),
+            title: match entry.change {
+                HistoryChange::Status => format!("{}: {}", entry.event, entry.to.label()),
+                HistoryChange::Added => format!("{}: added", entry.event),
+                HistoryChange::Removed => format!("{}: removed", entry.event),
+            },
+            summary: match entry.change {
+                HistoryChange::Status => {
+                    format!("{} changed from {} to {} (by {}).", entry.event, entry.from.label(), entry.to.label(), entry.by)
+                }
+                HistoryChange::Added => format!("{} was added to the lineup as {} (by {}).", entry.event, entry.to.label(), entry.by),
+                HistoryChange::Removed => format!("{} was removed from the lineup (by {}).", entry.event, entry.by),
+            },
+            at: entry.at,
+        })
+        .collect();
+
+    items.extend(
+        announcements
+            .lock()
+            .unwrap()
+            .active()
+            .filter(|announcement| event.is_none_or(|event| announcement.concerns(event)))
+            .map(|announcement| Item {
+                id: format!("{}:announcement/{}", TAG_AUTHORITY, announcement.id),
+                title: format!("Announcement: {}", announcement.message.lines().next().unwrap_or_default()),
+                summary: announcement.message.clone(),
+                at: announcement.created_at,
+            }),
+    );
+
+    items.sort_by_key(|item| Reverse(item.at));
+    items.truncate(FEED_LIMIT);
+
+    Feed { title, self_path, items }
+}
+
+fn atom(feed: &Feed, base: &BaseUrl) -> String {
+    let updated = feed.items.first().map_or_else(Utc::now, |item| item.at);
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!("  <id>{}:feed{}</id>\n", TAG_AUTHORITY, escape(&feed.self_path)));
+    out.push_str(&format!("  <title>{}</title>\n", escape(&feed.title)));
+    out.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    out.push_str(&format!("  <link rel=\"self\" href=\"{}{}\"/>\n", escape(&base.0), escape(&feed.self_path)));
+    out.push_str(&format!("  <link rel=\"alternate\" type=\"text/html\" href=\"{}/\"/>\n", escape(&base.0)));
+    out.push_str("  <author><name>Adharva</name></author>\n");
+
+    for item in &feed.items {
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <id>{}</id>\n", escape(&item.id)));
+        out.push_str(&format!("    <title>{}</title>\n", escape(&item.title)));
+        out.push_str(&format!("    <updated>{}</updated>\n", item.at.to_rfc3339()));
+        out.push_str(&format!("    <published>{}</published>\n", item.at.to_rfc3339()));
+        out.push_str(&format!("    <link href=\"{}/\"/>\n", escape(&base.0)));
+        out.push_str(&format!("    <summary>{}</summary>\n", escape(&item.summary)));
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+
+    out
+}
+
+fn rss(feed: &Feed, base: &BaseUrl) -> String {
+    let updated = feed.items.first().map_or_else(Utc::now, |item| item.at);
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    out.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+    out.push_str(&format!("  <title>{}</title>\n", escape(&feed.title)));
+    out.push_str(&format!("  <link>{}/</link>\n", escape(&base.0)));
+    out.push_str(&format!("  <description>{}</description>\n", escape(&feed.title)));
+    out.push_str(&format!(
+        "  <atom:link rel=\"self\" type=\"application/rss+xml\" href=\"{}{}\"/>\n",
+        escape(&base.0),
+        escape(&feed.self_path)
+    ));
+    out.push_str(&format!("  <lastBuildDate>{}</lastBuildDate>\n", updated.to_rfc2822()));
+
+    for item in &feed.items {
+        out.push_str("  <item>\n");
+        out.push_str(&format!("    <guid isPermaLink=\"false\">{}</guid>\n", escape(&item.id)));
+        out.push_str(&format!("    <title>{}</title>\n", escape(&item.title)));
+        out.push_str(&format!("    <link>{}/</link>\n", escape(&base.0)));
+        out.push_str(&format!("    <description>{}</description>\n", escape(&item.summary)));
+        out.push_str(&format!("    <pubDate>{}</pubDate>\n", item.at.to_rfc2822()));
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+
+    out
+}
+
+fn respond(feed: Feed, format: Format, base: &BaseUrl) -> (ContentType, String) {
+    match format {
+        Format::Atom => (ContentType::new("application", "atom+xml").with_params(("charset", "utf-8")), atom(&feed, base)),
+        Format::Rss => (ContentType::new("application", "rss+xml").with_params(("charset", "utf-8")), rss(&feed, base)),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/feed.atom", tag = "events",
+    responses((status = 200, description = "Atom feed of status changes and announcements", content_type = "application/atom+xml", body = String))
+)]
+#[get("/api/v3/feed.atom")]
+fn fest_atom(
+    base: BaseUrl,
+    history: &State<SharedHistory>,
+    announcements: &State<SharedAnnouncements>
+) -> (ContentType, String) {
+    let feed = collect("Adharva updates".to_string(), "/api/v3/feed.atom".to_string(), None, history, announcements);
+    respond(feed, Format::Atom, &base)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/feed.rss", tag = "events",
+    responses((status = 200, description = "RSS 2.0 feed of status changes and announcements", content_type = "application/rss+xml", body = String))
+)]
+#[get("/api/v3/feed.rss")]
+fn fest_rss(
+    base: BaseUrl,
+    history: &State<SharedHistory>,
+    announcements: &State<SharedAnnouncements>
+) -> (ContentType, String) {
+    let feed = collect("Adharva updates".to_string(), "/api/v3/feed.rss".to_string(), None, history, announcements);
+    respond(feed, Format::Rss, &base)
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/feeds/events/{file}", tag = "events",
+    params(("file" = String, Path, description = "Event name followed by .atom or .rss, e.g. Yukti.atom")),
+    responses((status = 200, description = "Feed of one event's status changes and the announcements concerning it", body = String), (status = 404))
+)]
+#[get("/api/v3/feeds/events/<file>")]
+fn event_feed(
+    file: &str,
+    base: BaseUrl,
+    events: &State<SharedEvents>,
+    history: &State<SharedHistory>,
+    announcements: &State<SharedAnnouncements>
+) -> Result<(ContentType, String), Status> {
+    let (event_name, extension) = file.rsplit_once('.').ok_or(Status::NotFound)?;
+    let format = Format::from_extension(extension).ok_or(Status::NotFound)?;
+    if !events.lock().unwrap().iter().any(|e| e.name == event_name) {
+        return Err(Status::NotFound);
+    }
+
+    let feed = collect(
+        format!("Adharva updates – {}", event_name),
+        // Percent-encoded, event names may hold spaces, `#` or `?`
+        uri!(event_feed(file)).to_string(),
+        Some(event_name),
+        history,
+        announcements,
+    );
+    Ok(respond(feed, format, &base))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![fest_atom, fest_rss, event_feed]
+}
@@ -0,0 +1,127 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use utoipa::ToSchema;
+
+use crate::stream::LiveUpdate;
+use crate::{persistence, EventStatus, RateLimitGuard};
+
+const HISTORY_FILE: &str = "history.json";
+const DEFAULT_LIMIT: usize = 100;
+
+pub type SharedHistory = Mutex<StatusHistory>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum HistoryChange {
+    #[default]
+    Status,
+    Added,   // `from` and `to` are both the status it was added with
+    Removed, // `from` and `to` are both the status it had
+}
+
+impl HistoryChange {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistoryChange::Status => "status",
+            HistoryChange::Added => "added",
+            HistoryChange::Removed => "removed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct HistoryEntry {
+    pub event: String,
+    #[serde(default)]
+    pub change: HistoryChange,
+    pub from: EventStatus,
+    pub to: EventStatus,
+    pub by: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct StatusHistory {
+    entries: Vec<HistoryEntry>, // oldest first
+}
+
+impl StatusHistory {
+    pub fn load() -> Self {
+        persistence::load(HISTORY_FILE).unwrap_or_default()
+    }
+
+    // Takes the updates produced for a mutation, ignoring those that aren't about the lineup
+    pub fn record(&mut self, changes: &[LiveUpdate]) -> std::io::Result<()> {
+        let at = Utc::now();
+        let before = self.entries.len();
+
+        for change in changes {
+            let (change, event, from, to, by) = match change {
+                LiveUpdate::StatusChanged { event, from, to, by } => (HistoryChange::Status, event, from, to, by),
+                LiveUpdate::EventAdded { event, status, by } => (HistoryChange::Added, event, status, status, by),
+                LiveUpdate::EventRemoved { event, status, by } => (HistoryChange::Removed, event, status, status, by),
+                _ => continue,
+            };
+            self.entries.push(HistoryEntry {
+                event: event.clone(),
+                change,
+                from: from.clone(),
+                to: to.clone(),
+                by: by.clone(),
+                at,
+            });
+        }
+
+        if self.entries.len() == before {
+            return Ok(());
+        }
+        persistence::save(HISTORY_FILE, self)
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(HISTORY_FILE, self)
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/history", tag = "events",
+    params(
+        ("event" = Option<String>, Query, description = "Only transitions of this event"),
+        ("limit" = Option<usize>, Query, description = "Most recent entries to return, default 100")
+    ),
+    responses((status = 200, description = "Status transitions, newest first", body = [HistoryEntry]))
+)]
+#[get("/api/v3/history?<event>&<limit>")]
+fn history(
+    event: Option<&str>,
+    limit: Option<usize>,
+    history: &State<SharedHistory>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Json<Vec<HistoryEntry>> {
+    let history = history.lock().unwrap();
+
+    Json(
+        history
+            .entries()
+            .iter()
+            .rev()
+            .filter(|entry| event.is_none_or(|event| entry.event == event))
+            .take(limit.unwrap_or(DEFAULT_LIMIT))
+            .cloned()
+            .collect(),
+    )
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![history]
+}
@@ -0,0 +1,200 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::tls::{rustls, util, Config as NativeTlsConfig, TlsListener};
+use rocket::http::{Header, Method, Status};
+use rocket::response::Redirect;
+use rocket::route::{Handler, Outcome};
+use rocket::serde::Deserialize;
+use rocket::tokio::{select, time::sleep};
+use rocket::{Data, Orbit, Request, Response, Rocket, Route};
+use tracing::{info, warn};
+
+const DEFAULT_CHECK_SECONDS: u64 = 30;
+
+fn default_check_seconds() -> u64 {
+    DEFAULT_CHECK_SECONDS
+}
+
+// The `https` table of the active Rocket.toml profile, only used when `tls` is configured
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HttpsConfig {
+    pub redirect_port: Option<u16>, // plain HTTP port that redirects everything to HTTPS
+    #[serde(default)]
+    pub hsts_max_age: u64, // seconds, 0 sends no Strict-Transport-Security header
+    #[serde(default)]
+    pub hsts_include_subdomains: bool,
+    #[serde(default = "default_check_seconds")]
+    pub reload_check_seconds: u64, // how often the cert and key files are checked for changes
+}
+
+impl Default for HttpsConfig {
+    fn default() -> Self {
+        HttpsConfig {
+            redirect_port: None,
+            hsts_max_age: 0,
+            hsts_include_subdomains: false,
+            reload_check_seconds: DEFAULT_CHECK_SECONDS,
+        }
+    }
+}
+
+impl HttpsConfig {
+    pub fn load() -> Self {
+        rocket::Config::figment().extract_inner("https").unwrap_or_default()
+    }
+}
+
+fn tls_paths(rocket: &Rocket<Orbit>) -> Option<(PathBuf, PathBuf)> {
+    let tls = rocket.config().tls.as_ref()?;
+    Some((tls.certs().left()?, tls.key().left()?))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// Runs the pair through the same listener setup Rocket uses at launch, so a relaunch cannot fail on it
+async fn validate(certs: &Path, key: &Path) -> io::Result<()> {
+    // An empty chain is accepted by the listener but fails every handshake
+    if util::load_certs(&mut BufReader::new(File::open(certs)?))?.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no certificate found"));
+    }
+
+    let config = NativeTlsConfig {
+        cert_chain: BufReader::new(File::open(certs)?),
+        private_key: BufReader::new(File::open(key)?),
+        ciphersuites: rustls::DEFAULT_CIPHER_SUITES.to_vec(),
+        prefer_server_order: true,
+        ca_certs: None,
+        mandatory_mtls: false,
+    };
+    TlsListener::bind(([127, 0, 0, 1], 0).into(), config).await.map(drop)
+}
+
+// Rocket 0.5 reads the certificate once when it binds, so a renewed one is picked up by shutting
+// down gracefully and letting `main` launch again in the same process
+pub struct CertReloader {
+    pub reload: Arc<AtomicBool>,
+    pub check_every: Duration,
+}
+
+#[rocket::async_trait]
+impl Fairing for CertReloader {
+    fn info(&self) -> Info {
+        Info { name: "TLS certificate reload", kind: Kind::Liftoff }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some((certs, key)) = tls_paths(rocket) else {
+            return;
+        };
+
+        let reload = self.reload.clone();
+        let check_every = self.check_every;
+        let mut shutdown = rocket.shutdown();
+        let seen = (modified(&certs), modified(&key));
+        let mut changed = None;
+
+        rocket::tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = sleep(check_every) => {}
+                    _ = &mut shutdown => return,
+                }
+
+                let current = (modified(&certs), modified(&key));
+                if current == seen {
+                    continue;
+                }
+                // certbot swaps the cert and key one after the other, so wait for both to settle
+                if changed.replace(current) != Some(current) {
+                    continue;
+                }
+                if let Err(error) = validate(&certs, &key).await {
+                    warn!(%error, "TLS certificate changed but cannot be loaded yet, keeping the current one");
+                    continue;
+                }
+
+                info!(certs = %certs.display(), "TLS certificate renewed, relaunching to load it");
+                reload.store(true, Ordering::SeqCst);
+                shutdown.clone().notify();
+                return;
+            }
+        });
+    }
+}
+
+pub struct Hsts {
+    header: Option<String>,
+}
+
+impl Hsts {
+    pub fn new(config: &HttpsConfig) -> Self {
+        let header = (config.hsts_max_age > 0).then(|| match config.hsts_include_subdomains {
+            true => format!("max-age={}; includeSubDomains", config.hsts_max_age),
+            false => format!("max-age={}", config.hsts_max_age),
+        });
+        Hsts { header }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Hsts {
+    fn info(&self) -> Info {
+        Info { name: "HSTS", kind: Kind::Response }
+    }
+
+    // Browsers ignore the header over plain HTTP, so it is only sent when TLS is on
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if let Some(header) = &self.header
+            && req.rocket().config().tls_enabled()
+        {
+            res.set_header(Header::new("Strict-Transport-Security", header.clone()));
+        }
+    }
+}
+
+#[derive(Clone)]
+struct RedirectToHttps {
+    https_port: u16,
+}
+
+#[rocket::async_trait]
+impl Handler for RedirectToHttps {
+    async fn handle<'r>(&self, req: &'r Request<'_>, _: Data<'r>) -> Outcome<'r> {
+        let Some(host) = req.host() else {
+            return Outcome::from(req, Status::BadRequest);
+        };
+        let authority = match self.https_port {
+            443 => host.domain().to_string(),
+            port => format!("{}:{}", host.domain(), port),
+        };
+
+        // 308 keeps the method and body, so a POST is retried as a POST over HTTPS
+        Outcome::from(req, Redirect::permanent(format!("https://{}{}", authority, req.uri())))
+    }
+}
+
+// A second, plain HTTP server on `redirect_port` that only redirects
+pub fn redirect_server(base: &rocket::Config, redirect_port: u16) -> Rocket<rocket::Build> {
+    let handler = RedirectToHttps { https_port: base.port };
+    let routes: Vec<Route> = [Method::Get, Method::Head, Method::Post, Method::Put, Method::Delete, Method::Patch]
+        .into_iter()
+        .map(|method| Route::new(method, "/<path..>", handler.clone()))
+        .collect();
+
+    let config = rocket::Config {
+        address: base.address,
+        port: redirect_port,
+        shutdown: base.shutdown.clone(),
+        ..rocket::Config::default()
+    };
+    rocket::custom(config).mount("/", routes)
+}
@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Serialize};
+use rocket::{Route, State};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::emergency::SharedEmergency;
+use crate::history::SharedHistory;
+use crate::registration::SharedRegistrations;
+use crate::schedule::{ScheduleEntry, SharedSchedule};
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{export, persistence, ApiKey, ApiKeys, EventDetail, EventStatus, SharedEvents, STATE_FILE};
+
+const UPLOAD_LIMIT_MIB: u64 = 2;
+const LOCAL_TIME_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"];
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RowError {
+    row: usize, // spreadsheet row number, the header is row 1
+    column: Option<String>,
+    message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct FieldChange {
+    field: &'static str,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct LineupChange {
+    kind: ChangeKind,
+    event: String,
+    fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportReport {
+    fingerprint: String, // pass back as `confirm` to apply exactly this diff
+    applied: bool,
+    errors: Vec<RowError>,
+    changes: Vec<LineupChange>,
+}
+
+struct Row {
+    line: usize,
+    name: String,
+    status: Option<EventStatus>,
+    category: Option<String>,
+    venue: Option<String>,
+    description: Option<String>,
+    times: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+struct Plan {
+    events: Vec<EventDetail>,
+    schedule: BTreeMap<String, ScheduleEntry>,
+    changes: Vec<LineupChange>,
+}
+
+fn parse_time(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+    LOCAL_TIME_FORMATS.iter().find_map(|format| {
+        let local = NaiveDateTime::parse_from_str(value, format).ok()?;
+        Some(tz.from_local_datetime(&local).earliest()?.with_timezone(&Utc))
+    })
+}
+
+// Reads the CSV by header name so column order doesn't matter; only `name` is required
+fn parse_rows(body: &str, tz: Tz, errors: &mut Vec<RowError>) -> Vec<Row> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(body.as_bytes());
+    let headers: Vec<String> = match reader.headers() {
+        Ok(headers) => headers.iter().map(|h| h.to_lowercase()).collect(),
+        Err(e) => {
+            errors.push(RowError { row: 1, column: None, message: e.to_string() });
+            return Vec::new();
+        }
+    };
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    if column("name").is_none() {
+        errors.push(RowError { row: 1, column: Some("name".to_string()), message: "missing required column".to_string() });
+        return Vec::new();
+    }
+
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(RowError { row, column: None, message: e.to_string() });
+                continue;
+            }
+        };
+        let field = |name: &str| {
+            column(name)
+                .and_then(|i| record.get(i))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let mut fail = |column: &str, message: &str| {
+            errors.push(RowError { row, column: Some(column.to_string()), message: message.to_string() });
+        };
+
+        let Some(name) = field("name") else {
+            fail("name", "event name is empty");
+            continue;
+        };
+        if !seen.insert(name.clone()) {
+            fail("name", "event appears more than once");
+            continue;
+        }
+
+        let status = match field("status").map(|s| s.parse::<EventStatus>()) {
+            Some(Ok(status)) => Some(status),
+            Some(Err(())) => {
+                fail("status", "not a valid status");
+                continue;
+            }
+            None => None,
+        };
+
+        let starts_at = field("starts_at").map(|value| parse_time(&value, tz));
+        let ends_at = field("ends_at").map(|value| parse_time(&value, tz));
+        let times = match (starts_at, ends_at) {
+            (Some(Some(start)), Some(Some(end))) if end > start => Some((start, end)),
+            (Some(Some(_)), Some(Some(_))) => {
+                fail("ends_at", "event ends before it starts");
+                continue;
+            }
+            (Some(None), _) => {
+                fail("starts_at", "expected RFC 3339 or YYYY-MM-DD HH:MM");
+                continue;
+            }
+            (_, Some(None)) => {
+                fail("ends_at", "expected RFC 3339 or YYYY-MM-DD HH:MM");
+                continue;
+            }
+            (None, None) => None,
+            _ => {
+                fail("starts_at", "starts_at and ends_at must be given together");
+                continue;
+            }
+        };
+
+        rows.push(Row {
+            line: row,
+            name,
+            status,
+            category: field("category"),
+            venue: field("venue"),
+            description: field("description"),
+            times,
+        });
+    }
+
+    rows
+}
+
+fn diff_field(fields: &mut Vec<FieldChange>, field: &'static str, from: Option<String>, to: Option<String>) {
+    if from != to {
+        fields.push(FieldChange { field, from, to });
+    }
+}
+
+// Statuses in the file only seed new events, the live status of existing events is left alone
+fn plan(
+    rows: Vec<Row>,
+    prune: bool,
+    events: &[EventDetail],
+    schedule: &BTreeMap<String, ScheduleEntry>,
+    errors: &mut Vec<RowError>
+) -> Plan {
+    let now = Utc::now();
+    let names: HashSet<String> = rows.iter().map(|row| row.name.clone()).collect();
+    let mut new_events: Vec<EventDetail> = events
+        .iter()
+        .filter(|event| !prune || names.contains(&event.name))
+        .cloned()
+        .collect();
+    let mut new_schedule = schedule.clone();
+    let mut changes = Vec::new();
+
+    for row in rows {
+        let existing = events.iter().find(|event| event.name == row.name);
+        let current = schedule.get(&row.name);
+        let mut fields = Vec::new();
+
+        if existing.is_none() {
+            let status = row.status.clone().unwrap_or(EventStatus::Soon);
+            diff_field(&mut fields, "status", None, Some(status.label().to_string()));
+            new_events.push(EventDetail { name: row.name.clone(), status });
+        }
+
+        let (starts_at, ends_at) = match (row.times, current) {
+            (Some(times), _) => times,
+            (None, Some(entry)) => (entry.starts_at, entry.ends_at),
+            (None, None) => {
+                if row.category.is_some() || row.venue.is_some() || row.description.is_some() {
+                    errors.push(RowError {
+                        row: row.line,
+                        column: Some("starts_at".to_string()),
+                        message: "category, venue and description need a schedule".to_string(),
+                    });
+                }
+                if existing.is_none() {
+                    changes.push(LineupChange { kind: ChangeKind::Added, event: row.name, fields });
+                }
+                continue;
+            }
+        };
+
+        let time = |at: DateTime<Utc>| Some(at.to_rfc3339());
+        diff_field(&mut fields, "category", current.and_then(|e| e.category.clone()), row.category.clone());
+        diff_field(&mut fields, "venue", current.and_then(|e| e.venue.clone()), row.venue.clone());
+        diff_field(&mut fields, "description", current.and_then(|e| e.description.clone()), row.description.clone());
+        diff_field(&mut fields, "starts_at", current.and_then(|e| time(e.starts_at)), time(starts_at));
+        diff_field(&mut fields, "ends_at", current.and_then(|e| time(e.ends_at)), time(ends_at));
+
+        if fields.is_empty() {
+            continue;
+        }
+        new_schedule.insert(row.name.clone(), ScheduleEntry {
+            category: row.category,
+            venue: row.venue,
+            description: row.description,
+            starts_at,
+            ends_at,
+            cancelled: current.is_some_and(|e| e.cancelled),
+            delayed_until: current.and_then(|e| e.delayed_until),
+            revision: current.map_or(0, |e| e.revision + 1),
+            updated_at: now,
+        });
+
+        let kind = if existing.is_some() { ChangeKind::Updated } else { ChangeKind::Added };
+        changes.push(LineupChange { kind, event: row.name, fields });
+    }
+
+    if prune {
+        for event in events.iter().filter(|event| !names.contains(&event.name)) {
+            new_schedule.remove(&event.name);
+            changes.push(LineupChange { kind: ChangeKind::Removed, event: event.name.clone(), fields: Vec::new() });
+        }
+    }
+
+    Plan { events: new_events, schedule: new_schedule, changes }
+}
+
+// Ties a dry run to the exact upload and server state it was computed against
+fn fingerprint(body: &str, prune: bool, tz: Tz, events: &[EventDetail], schedule: &BTreeMap<String, ScheduleEntry>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(body.as_bytes());
+    hasher.update(format!("\n{}\n{}\n", prune, tz).as_bytes());
+    hasher.update(serde_json::to_vec(events).unwrap_or_default());
+    hasher.update(serde_json::to_vec(schedule).unwrap_or_default());
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/import", tag = "schedule",
+    params(
+        ("confirm" = Option<String>, Query, description = "Fingerprint from a dry run; applies the import when it still matches"),
+        ("prune" = Option<bool>, Query, description = "Remove events missing from the file along with their registrations, default false"),
+        ("tz" = Option<String>, Query, description = "IANA time zone for times without an offset, default EXPORT_TIMEZONE or UTC")
+    ),
+    request_body(content = String, content_type = "text/csv", description = "Columns: name, status, category, venue, description, starts_at, ends_at"),
+    responses(
+        (status = 200, description = "Dry-run diff, or the applied diff when confirmed", body = ImportReport),
+        (status = 403),
+        (status = 409, description = "The upload or server state changed since the dry run", body = ImportReport),
+        (status = 413, description = "Upload too large"),
+        (status = 422, description = "Row-level validation errors, nothing applied", body = ImportReport),
+        (status = 423, description = "An emergency override is active")
+    ),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/import?<confirm>&<prune>&<tz>", data = "<upload>")]
+#[allow(clippy::too_many_arguments)]
+async fn import_lineup(
+    upload: Data<'_>,
+    confirm: Option<&str>,
+    prune: Option<bool>,
+    tz: Option<&str>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>,
+    emergency: &State<SharedEmergency>,
+    history: &State<SharedHistory>,
+    registrations: &State<SharedRegistrations>,
+    updates: &State<LiveUpdates>
+) -> Result<(Status, Json<ImportReport>), Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let body = upload.open(UPLOAD_LIMIT_MIB.mebibytes()).into_string().await.map_err(|_| Status::BadRequest)?;
+    if !body.is_complete() {
+        return Err(Status::PayloadTooLarge);
+    }
+    let tz = match tz {
+        Some(tz) => tz.parse().map_err(|_| Status::UnprocessableEntity)?,
+        None => export::default_timezone(),
+    };
+    let prune = prune.unwrap_or(false);
+
+    let mut errors = Vec::new();
+    let rows = parse_rows(&body, tz, &mut errors);
+
+    // Lock order as documented on SharedEvents
+    let emergency = emergency.lock().unwrap();
+    let mut events = events.lock().unwrap();
+    let mut schedule = schedule.lock().unwrap();
+    let fingerprint = fingerprint(&body, prune, tz, &events, schedule.entries());
+    let plan = plan(rows, prune, &events, schedule.entries(), &mut errors);
+    errors.sort_by_key(|error| error.row);
+
+    let mut report = ImportReport { fingerprint, applied: false, errors, changes: plan.changes };
+    if !report.errors.is_empty() {
+        return Ok((Status::UnprocessableEntity, Json(report)));
+    }
+    let Some(confirm) = confirm else {
+        return Ok((Status::Ok, Json(report)));
+    };
+    if confirm != report.fingerprint {
+        return Ok((Status::Conflict, Json(report)));
+    }
+    if emergency.is_some() {
+        return Err(Status::Locked);
+    }
+
+    // Both files are written before memory changes; the schedule is rolled back if the events fail to save
+    let previous_schedule = schedule.entries().clone();
+    schedule.replace(plan.schedule)?;
+    if persistence::save(STATE_FILE, &plan.events).is_err() {
+        schedule.replace(previous_schedule)?;
+        return Err(Status::InternalServerError);
+    }
+    let before = std::mem::replace(&mut *events, plan.events);
+
+    let changes = LiveUpdate::lineup_changes(&before, &events, &api_keys.identity(&api_key));
+    if let Err(error) = history.lock().unwrap().record(&changes) {
+        warn!(%error, "failed to record lineup changes in the history");
+    }
+    let removed: Vec<&str> = changes
+        .iter()
+        .filter_map(|change| match change {
+            LiveUpdate::EventRemoved { event, .. } => Some(event.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut registrations = registrations.lock().unwrap();
+    if registrations.remove_events(&removed) > 0 && registrations.save().is_err() {
+        warn!("failed to save registrations after removing events");
+    }
+
+    info!(changes = report.changes.len(), prune, "lineup imported");
+    updates.publish(LiveUpdate::Events { events: events.clone() });
+    for change in changes {
+        updates.publish(change);
+    }
+    report.applied = true;
+
+    Ok((Status::Ok, Json(report)))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![import_lineup]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KOLKATA: Tz = chrono_tz::Asia::Kolkata;
+
+    fn event(name: &str, status: EventStatus) -> EventDetail {
+        EventDetail { name: name.to_string(), status }
+    }
+
+    #[test]
+    fn row_errors_carry_spreadsheet_row_numbers() {
+        let body = "name,status,starts_at,ends_at\n\
+                    Yukti,round9,,\n\
+                    Natya,,2026-03-01 10:00,2026-03-01 09:00\n\
+                    Naada,,2026-03-01 10:00,\n\
+                    Naada,,,\n\
+                    Nataka,Started,2026-03-01 10:00,2026-03-01T12:00:00Z\n";
+        let mut errors = Vec::new();
+        let rows = parse_rows(body, KOLKATA, &mut errors);
+
+        let found: Vec<(usize, Option<&str>)> = errors.iter().map(|e| (e.row, e.column.as_deref())).collect();
+        assert_eq!(found, vec![(2, Some("status")), (3, Some("ends_at")), (4, Some("starts_at")), (5, Some("name"))]);
+
+        // Times without an offset are read in the given zone, IST is 5:30 ahead of UTC
+        assert_eq!(rows.len(), 1);
+        let (starts_at, ends_at) = rows[0].times.unwrap();
+        assert_eq!(starts_at.to_rfc3339(), "2026-03-01T04:30:00+00:00");
+        assert_eq!(ends_at.to_rfc3339(), "2026-03-01T12:00:00+00:00");
+    }
+
+    #[test]
+    fn missing_name_column_rejects_the_file() {
+        let mut errors = Vec::new();
+        assert!(parse_rows("event,status\nYukti,Soon\n", KOLKATA, &mut errors).is_empty());
+        assert_eq!((errors[0].row, errors[0].column.as_deref()), (1, Some("name")));
+    }
+
+    #[test]
+    fn import_keeps_live_statuses_and_prunes_only_when_asked() {
+        let events = vec![event("Yukti", EventStatus::Round2), event("Natya", EventStatus::Soon)];
+        let schedule = BTreeMap::new();
+        let rows = || {
+            let mut errors = Vec::new();
+            let rows = parse_rows("name,status\nYukti,Ended\nNazakat,\n", KOLKATA, &mut errors);
+            assert!(errors.is_empty());
+            rows
+        };
+
+        let kept = plan(rows(), false, &events, &schedule, &mut Vec::new());
+        let statuses: Vec<(&str, &EventStatus)> = kept.events.iter().map(|e| (e.name.as_str(), &e.status)).collect();
+        assert_eq!(statuses, vec![("Yukti", &EventStatus::Round2), ("Natya", &EventStatus::Soon), ("Nazakat", &EventStatus::Soon)]);
+        assert_eq!(kept.changes.len(), 1);
+
+        let pruned = plan(rows(), true, &events, &schedule, &mut Vec::new());
+        assert!(!pruned.events.iter().any(|e| e.name == "Natya"));
+        assert!(pruned.changes.iter().any(|c| c.event == "Natya" && matches!(c.kind, ChangeKind::Removed)));
+    }
+
+    #[test]
+    fn fingerprint_changes_with_the_upload_options_or_server_state() {
+        let body = "name\nYukti\n";
+        let events = vec![event("Yukti", EventStatus::Soon)];
+        let schedule = BTreeMap::new();
+        let dry_run = fingerprint(body, false, KOLKATA, &events, &schedule);
+
+        assert_eq!(dry_run, fingerprint(body, false, KOLKATA, &events, &schedule));
+        assert_ne!(dry_run, fingerprint("name\nNatya\n", false, KOLKATA, &events, &schedule));
+        assert_ne!(dry_run, fingerprint(body, true, KOLKATA, &events, &schedule));
+        assert_ne!(dry_run, fingerprint(body, false, Tz::UTC, &events, &schedule));
+        assert_ne!(dry_run, fingerprint(body, false, KOLKATA, &[event("Yukti", EventStatus::Started)], &schedule));
+    }
+}
@@ -0,0 +1,631 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use dotenvy::var;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::accounts::{self, AccountInfo, Accounts};
+use crate::tokens::{self, Tokens};
+use crate::totp::SecondFactors;
+use crate::{audit, logging, metrics, persistence, SharedEvents};
+
+const KEYS_FILE: &str = "keys.json";
+const SECRET_PREFIX: &str = "adh_";
+const DEFAULT_OVERLAP_HOURS: i64 = 24;
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60; // last_used_at is only written to disk this often per key
+
+// Env vars that seeded per-event keys before keys.json existed
+const ENV_EVENT_KEYS: [(&str, &str); 5] = [
+    ("YUKTI_API_KEY", "Yukti"),
+    ("NATYA_API_KEY", "Natya-Sutra"),
+    ("NAADA_API_KEY", "Naada-Nirvana"),
+    ("NAZAKAT_API_KEY", "Nazakat"),
+    ("NATAKA_API_KEY", "Nataka"),
+];
+
+// What a key may do, written as "admin" or "event:<name>"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", try_from = "String", into = "String")]
+pub enum Scope {
+    Admin,
+    Event(String),
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.split_once(':') {
+            None if input == "admin" => Ok(Scope::Admin),
+            Some(("event", event)) if !event.is_empty() => Ok(Scope::Event(event.to_string())),
+            _ => Err(format!("unknown scope {:?}, expected \"admin\" or \"event:<name>\"", input)),
+        }
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Admin => write!(f, "admin"),
+            Scope::Event(event) => write!(f, "event:{}", event),
+        }
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum KeyState {
+    Active,
+    Expired,
+    Revoked,
+}
+
+// Only a hash of the secret is kept, the secret itself is shown once when the key is issued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct KeyRecord {
+    id: String,
+    name: String, // recorded as the author of changes made with this key
+    secret_hash: String,
+    prefix: Option<String>, // start of a generated secret, to recognise it without revealing it
+    scopes: Vec<Scope>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    replaced_by: Option<String>, // id of the key issued when this one was rotated
+}
+
+impl KeyRecord {
+    fn issue(name: String, scopes: Vec<Scope>, expires_at: Option<DateTime<Utc>>) -> (Self, String) {
+        let random: String = rand::thread_rng().sample_iter(&Alphanumeric).take(40).map(char::from).collect();
+        let secret = format!("{}{}", SECRET_PREFIX, random);
+        (Self::with_secret(name, scopes, expires_at, &secret), secret)
+    }
+
+    fn with_secret(name: String, scopes: Vec<Scope>, expires_at: Option<DateTime<Utc>>, secret: &str) -> Self {
+        KeyRecord {
+            id: rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect(),
+            name,
+            secret_hash: hash(secret),
+            // Secrets seeded from env vars can be short enough for any prefix to give them away
+            prefix: secret
+                .starts_with(SECRET_PREFIX)
+                .then(|| secret.chars().take(SECRET_PREFIX.len() + 4).collect()),
+            scopes,
+            created_at: Utc::now(),
+            expires_at,
+            last_used_at: None,
+            revoked_at: None,
+            replaced_by: None,
+        }
+    }
+
+    fn state(&self, now: DateTime<Utc>) -> KeyState {
+        if self.revoked_at.is_some() {
+            KeyState::Revoked
+        } else if self.expires_at.is_some_and(|at| at <= now) {
+            KeyState::Expired
+        } else {
+            KeyState::Active
+        }
+    }
+
+    fn is_admin(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+
+    fn info(&self, now: DateTime<Utc>) -> KeyInfo {
+        KeyInfo {
+            id: self.id.clone(),
+            identity: self.name.clone(),
+            admin: self.is_admin(),
+            events: self
+                .scopes
+                .iter()
+                .filter_map(|scope| match scope {
+                    Scope::Event(event) => Some(event.clone()),
+                    Scope::Admin => None,
+                })
+                .collect(),
+            scopes: self.scopes.iter().map(Scope::to_string).collect(),
+            prefix: self.prefix.clone(),
+            state: self.state(now),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+            revoked_at: self.revoked_at,
+            replaced_by: self.replaced_by.clone(),
+        }
+    }
+}
+
+pub fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct KeyStore {
+    keys: Vec<KeyRecord>,
+}
+
+impl KeyStore {
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(KEYS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    fn get(&self, id: &str) -> Option<&KeyRecord> {
+        self.keys.iter().find(|key| key.id == id)
+    }
+
+    fn active_admins(&self, now: DateTime<Utc>) -> usize {
+        self.keys.iter().filter(|key| key.is_admin() && key.state(now) == KeyState::Active).count()
+    }
+}
+
+// Who presented the credentials behind a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    Key(String), // key id
+    Token { key: String, family: String }, // an access token exchanged from that key
+    Account { user: String, session: String },
+}
+
+// The credentials accepted for a request, with the name and scopes they carried when it arrived.
+// Despite the name it also stands for a coordinator logged in with a session cookie
+pub struct ApiKey {
+    pub principal: Principal,
+    name: String,
+    scopes: Vec<Scope>,
+    second_factor: bool, // a TOTP or backup code was checked for this request or its session
+}
+
+impl ApiKey {
+    pub fn new(principal: Principal, name: String, scopes: Vec<Scope>) -> Self {
+        ApiKey { principal, name, scopes, second_factor: false }
+    }
+
+    pub fn with_second_factor(mut self, verified: bool) -> Self {
+        self.second_factor |= verified;
+        self
+    }
+
+    pub fn has_second_factor(&self) -> bool {
+        self.second_factor
+    }
+
+    // Admin scope alone is not enough to act as an admin, see `ApiKeys::is_root`
+    pub fn has_admin_scope(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+}
+
+// Scopes given to a key or an account must name events that exist
+pub fn valid_scopes(scopes: &[Scope], events: &SharedEvents) -> bool {
+    let events = events.lock().unwrap();
+    !scopes.is_empty()
+        && scopes.iter().all(|scope| match scope {
+            Scope::Admin => true,
+            Scope::Event(event) => events.iter().any(|e| &e.name == event),
+        })
+}
+
+pub struct ApiKeys {
+    store: Mutex<KeyStore>,
+    env_ignored: bool, // keys.json exists, so API_SECRET_KEY and the per-event variables are not used
+}
+
+impl ApiKeys {
+    // keys.json is the source of truth, the env keys only seed it the first time the server starts
+    pub fn load() -> Self {
+        if let Some(store) = persistence::load::<KeyStore>(KEYS_FILE) {
+            let env_ignored = var("API_SECRET_KEY").is_ok() || ENV_EVENT_KEYS.iter().any(|(name, _)| var(name).is_ok());
+            return ApiKeys { store: Mutex::new(store), env_ignored };
+        }
+
+        let root_key = var("API_SECRET_KEY").expect("API_SECRET_KEY not set and no keys.json to load keys from");
+        let mut keys = vec![KeyRecord::with_secret("root".to_string(), vec![Scope::Admin], None, &root_key)];
+        for (env, event) in ENV_EVENT_KEYS {
+            if let Ok(key) = var(env) {
+                keys.push(KeyRecord::with_secret(event.to_string(), vec![Scope::Event(event.to_string())], None, &key));
+            }
+        }
+
+        let store = KeyStore { keys };
+        store.save().expect("Failed to initialize keys.json");
+        ApiKeys { store: Mutex::new(store), env_ignored: false }
+    }
+
+    pub fn env_ignored(&self) -> bool {
+        self.env_ignored
+    }
+
+    pub fn has_admin(&self) -> bool {
+        self.store.lock().unwrap().active_admins(Utc::now()) > 0
+    }
+
+    pub fn has_event_keys(&self) -> bool {
+        let now = Utc::now();
+        self.store.lock().unwrap().keys.iter().any(|key| !key.is_admin() && key.state(now) == KeyState::Active)
+    }
+
+    // The error names the metrics reason for a rejected key
+    fn authenticate(&self, secret: &str) -> Result<ApiKey, &'static str> {
+        let now = Utc::now();
+        let secret_hash = hash(secret);
+
+        let mut store = self.store.lock().unwrap();
+        let key = store.keys.iter_mut().find(|key| key.secret_hash == secret_hash).ok_or("invalid_key")?;
+        match key.state(now) {
+            KeyState::Revoked => return Err("revoked_key"),
+            KeyState::Expired => return Err("expired_key"),
+            KeyState::Active => (),
+        }
+
+        let stale = key.last_used_at.is_none_or(|at| now - at >= Duration::seconds(LAST_USED_RESOLUTION_SECONDS));
+        key.last_used_at = Some(now);
+        let api_key = ApiKey::new(Principal::Key(key.id.clone()), key.name.clone(), key.scopes.clone());
+        if stale {
+            // Losing a last-used timestamp is not worth failing the request over
+            let _ = store.save();
+        }
+
+        Ok(api_key)
+    }
+
+    // The current name and scopes of an active key with its expiry, for credentials derived from it
+    pub fn active(&self, id: &str) -> Option<(String, Vec<Scope>, Option<DateTime<Utc>>)> {
+        let store = self.store.lock().unwrap();
+        let key = store.get(id).filter(|key| key.state(Utc::now()) == KeyState::Active)?;
+        Some((key.name.clone(), key.scopes.clone(), key.expires_at))
+    }
+
+    // Admin scope only counts once a second factor has been checked, event scopes never need one
+    pub fn is_root(&self, key: &ApiKey) -> bool {
+        key.has_admin_scope() && key.second_factor
+    }
+
+    // Refuses a request the key or account may not make, counting why in auth_failures_total
+    pub fn forbidden(&self, key: &ApiKey) -> Status {
+        let reason = if key.has_admin_scope() && !key.second_factor {
+            "second_factor_required"
+        } else {
+            "insufficient_scope"
+        };
+        metrics::METRICS.auth_failure(reason);
+        Status::Forbidden
+    }
+
+    // Names the holder of a key or account for authorship
+    pub fn identity(&self, key: &ApiKey) -> String {
+        key.name.clone()
+    }
+
+    // Admins can update any event, otherwise the key or account needs that event's scope
+    pub fn can_edit(&self, key: &ApiKey, event_name: &str) -> bool {
+        self.is_root(key) || key.scopes.iter().any(|scope| matches!(scope, Scope::Event(e) if e == event_name))
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(KEYS_FILE, &*self.store.lock().unwrap())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let api_keys = match req.rocket().state::<ApiKeys>() {
+            Some(state) => state,
+            None => return Outcome::Error((Status::InternalServerError, ())),
+        };
+
+        // Extract Authorization: Bearer <key>, falling back to a browser session
+        let secret = req.headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        let authenticated = match (secret, req.cookies().get(accounts::SESSION_COOKIE)) {
+            (Some(token), _) if tokens::is_access_token(token) => match req.rocket().state::<Tokens>() {
+                Some(tokens) => tokens.authenticate(token, api_keys).map_err(|reason| (Status::Unauthorized, reason)),
+                None => return Outcome::Error((Status::InternalServerError, ())),
+            },
+            (Some(secret), _) => api_keys.authenticate(secret).map_err(|reason| (Status::Unauthorized, reason)),
+            (None, Some(cookie)) => match req.rocket().state::<Accounts>() {
+                Some(accounts) => accounts.authenticate(cookie.value(), req),
+                None => return Outcome::Error((Status::InternalServerError, ())),
+            },
+            (None, None) => Err((Status::Unauthorized, "missing_key")),
+        };
+
+        match authenticated {
+            Ok(key) => {
+                let key = match req.rocket().state::<SecondFactors>() {
+                    Some(second_factors) => second_factors.check(key, req),
+                    None => key,
+                };
+                let identity = api_keys.identity(&key);
+                logging::request_span(req).span.record("key", identity.as_str());
+                req.local_cache(|| audit::Actor(Some(identity)));
+                Outcome::Success(key)
+            }
+            Err((status, reason)) => {
+                metrics::METRICS.auth_failure(reason);
+                Outcome::Error((status, ()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct KeyInfo {
+    id: String,
+    identity: String,
+    admin: bool,
+    events: Vec<String>, // events this key may edit, empty for admin keys which may edit all
+    scopes: Vec<String>,
+    prefix: Option<String>,
+    state: KeyState,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    replaced_by: Option<String>,
+}
+
+// The only response that ever contains a secret
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct IssuedKey {
+    key: KeyInfo,
+    secret: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewKey {
+    name: String,
+    #[schema(value_type = Vec<String>, example = json!(["event:Yukti"]))]
+    scopes: Vec<Scope>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// Both carry `identity`, `admin` and `events`
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum Whoami {
+    Key(KeyInfo),
+    Account(AccountInfo),
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/whoami", tag = "keys",
+    responses((status = 200, description = "The key that was presented or that the access token was exchanged from, or the account of the session cookie", body = Whoami), (status = 401)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[get("/api/v3/whoami")]
+fn whoami(api_key: ApiKey, api_keys: &State<ApiKeys>, accounts: &State<Accounts>) -> Result<Json<Whoami>, Status> {
+    match &api_key.principal {
+        Principal::Key(id) | Principal::Token { key: id, .. } => {
+            let store = api_keys.store.lock().unwrap();
+            let record = store.get(id).ok_or(Status::Unauthorized)?;
+            Ok(Json(Whoami::Key(record.info(Utc::now()))))
+        }
+        Principal::Account { user, .. } => accounts.info(user).map(|info| Json(Whoami::Account(info))).ok_or(Status::Unauthorized),
+    }
+}
+
+// Lists keys with their metadata, never the secrets themselves
+#[utoipa::path(
+    get, path = "/api/v3/admin/keys", tag = "keys",
+    responses((status = 200, body = [KeyInfo]), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/keys")]
+fn list_keys(api_key: ApiKey, api_keys: &State<ApiKeys>) -> Result<Json<Vec<KeyInfo>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let now = Utc::now();
+    let store = api_keys.store.lock().unwrap();
+    let mut keys: Vec<KeyInfo> = store.keys.iter().map(|key| key.info(now)).collect();
+    keys.sort_by(|a, b| (!a.admin, &a.identity, a.created_at).cmp(&(!b.admin, &b.identity, b.created_at)));
+
+    Ok(Json(keys))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/keys", tag = "keys", request_body = NewKey,
+    responses((status = 200, description = "The new key and its secret, which is not shown again", body = IssuedKey), (status = 403), (status = 422, description = "Empty name or scopes, an unknown event, or an expiry in the past")),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/keys", data = "<request>")]
+fn issue_key(
+    request: Json<NewKey>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>
+) -> Result<Json<IssuedKey>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let request = request.into_inner();
+    if request.name.trim().is_empty()
+        || request.expires_at.is_some_and(|at| at <= Utc::now())
+        || !valid_scopes(&request.scopes, events)
+    {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let (record, secret) = KeyRecord::issue(request.name.trim().to_string(), request.scopes, request.expires_at);
+    let info = record.info(Utc::now());
+
+    let mut store = api_keys.store.lock().unwrap();
+    store.keys.push(record);
+    store.save()?;
+
+    Ok(Json(IssuedKey { key: info, secret }))
+}
+
+// Any key may rotate itself, the root key may rotate any key
+#[utoipa::path(
+    post, path = "/api/v3/admin/keys/{id}/rotate", tag = "keys",
+    params(
+        ("id" = String, Path, description = "Key id"),
+        ("overlap_hours" = Option<i64>, Query, description = "How long the old secret keeps working, default 24")
+    ),
+    responses((status = 200, description = "The replacement key and its secret, with the same name, scopes, lifetime and second factor", body = IssuedKey), (status = 403), (status = 404), (status = 409, description = "The key is revoked or expired"), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/keys/<id>/rotate?<overlap_hours>")]
+fn rotate_key(
+    id: &str,
+    overlap_hours: Option<i64>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    second_factors: &State<SecondFactors>
+) -> Result<Json<IssuedKey>, Status> {
+    // A leaked admin key must not be able to rotate its owner out
+    let own = api_key.principal == Principal::Key(id.to_string()) && (!api_key.has_admin_scope() || api_key.has_second_factor());
+    if !own && !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    let now = Utc::now();
+    let overlap_ends = Duration::try_hours(overlap_hours.unwrap_or(DEFAULT_OVERLAP_HOURS))
+        .filter(|overlap| *overlap >= Duration::zero())
+        .and_then(|overlap| now.checked_add_signed(overlap))
+        .ok_or(Status::UnprocessableEntity)?;
+
+    let mut store = api_keys.store.lock().unwrap();
+    let previous = store.keys.clone();
+    let old = store.keys.iter_mut().find(|key| key.id == id).ok_or(Status::NotFound)?;
+    if old.state(now) != KeyState::Active {
+        return Err(Status::Conflict);
+    }
+
+    let expires_at = match old.expires_at {
+        Some(at) => Some(now.checked_add_signed(at - old.created_at).ok_or(Status::UnprocessableEntity)?),
+        None => None,
+    };
+    let (record, secret) = KeyRecord::issue(old.name.clone(), old.scopes.clone(), expires_at);
+    old.expires_at = Some(old.expires_at.map_or(overlap_ends, |at| at.min(overlap_ends)));
+    old.replaced_by = Some(record.id.clone());
+
+    let info = record.info(now);
+    store.keys.push(record);
+
+    // The replacement is useless for admin work without the old key's second factor, so both land or neither does
+    if let Err(status) = store.save().and_then(|_| second_factors.carry_over(id, &info.id)) {
+        store.keys = previous;
+        let _ = store.save();
+        return Err(status);
+    }
+
+    Ok(Json(IssuedKey { key: info, secret }))
+}
+
+// Revoked keys stay listed so past authorship can still be traced
+#[utoipa::path(
+    delete, path = "/api/v3/admin/keys/{id}", tag = "keys",
+    params(("id" = String, Path, description = "Key id")),
+    responses((status = 200, description = "The revoked key, it stops working immediately", body = KeyInfo), (status = 403), (status = 404), (status = 409, description = "It is the last active admin key")),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/keys/<id>")]
+fn revoke_key(id: &str, api_key: ApiKey, api_keys: &State<ApiKeys>) -> Result<Json<KeyInfo>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let now = Utc::now();
+    let mut store = api_keys.store.lock().unwrap();
+    let last_admin = store.active_admins(now) == 1;
+    let key = store.keys.iter_mut().find(|key| key.id == id).ok_or(Status::NotFound)?;
+    if key.state(now) == KeyState::Active && key.is_admin() && last_admin {
+        return Err(Status::Conflict);
+    }
+
+    key.revoked_at.get_or_insert(now);
+    let info = key.info(now);
+    store.save()?;
+
+    Ok(Json(info))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![whoami, list_keys, issue_key, rotate_key, revoke_key]
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+
+    use super::*;
+    use crate::totp::StepUpHint;
+
+    const ADMIN_SECRET: &str = "adk_test-admin-secret";
+
+    // Marked as just used so authenticating does not write keys.json
+    fn admin_keys() -> ApiKeys {
+        let mut key = KeyRecord::with_secret("root".to_string(), vec![Scope::Admin], None, ADMIN_SECRET);
+        key.last_used_at = Some(Utc::now());
+        ApiKeys { store: Mutex::new(KeyStore { keys: vec![key] }), env_ignored: false }
+    }
+
+    #[test]
+    fn admin_key_without_second_factor_is_forbidden() {
+        let rocket = rocket::build()
+            .manage(admin_keys())
+            .manage(SecondFactors::default())
+            .attach(StepUpHint)
+            .mount("/", routes![list_keys]);
+        let client = Client::untracked(rocket).unwrap();
+
+        let response = client
+            .get("/api/v3/admin/keys")
+            .header(Header::new("Authorization", format!("Bearer {}", ADMIN_SECRET)))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.headers().get_one("X-OTP-Required"), Some("enroll"));
+    }
+
+    #[test]
+    fn admin_scope_needs_a_second_factor_to_be_root() {
+        let api_keys = admin_keys();
+        let admin = || ApiKey::new(Principal::Key("key1".to_string()), "root".to_string(), vec![Scope::Admin]);
+        let event = ApiKey::new(Principal::Key("key2".to_string()), "Yukti".to_string(), vec![Scope::Event("Yukti".to_string())]);
+
+        assert!(!api_keys.is_root(&admin()));
+        assert!(!api_keys.can_edit(&admin(), "Yukti"));
+        assert!(api_keys.is_root(&admin().with_second_factor(true)));
+        assert!(!api_keys.is_root(&event.with_second_factor(true)));
+    }
+}
@@ -0,0 +1,109 @@
+use dotenvy::var;
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use tracing::{field, info, info_span, Span};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Keeps the background file writer alive, dropping it flushes pending lines
+pub struct LogGuard(#[allow(dead_code)] WorkerGuard);
+
+// LOG_FILTER takes EnvFilter directives, e.g. "info,adharva_event_server::webhooks=debug"
+pub fn init() -> LogGuard {
+    let filter = EnvFilter::try_new(var("LOG_FILTER").unwrap_or_else(|_| "info".to_string()))
+        .expect("LOG_FILTER is not a valid filter");
+    let directory = var("LOG_DIR").unwrap_or_else(|_| "logs".to_string());
+    let rotation = match var("LOG_ROTATION").as_deref() {
+        Ok("hourly") => Rotation::HOURLY,
+        Ok("never") => Rotation::NEVER,
+        _ => Rotation::DAILY,
+    };
+
+    let appender = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix("requests")
+        .filename_suffix("jsonl")
+        .build(&directory)
+        .expect("Failed to create log directory");
+    let (file_writer, guard) = tracing_appender::non_blocking(appender);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().json().with_current_span(true).with_span_list(false))
+        .with(fmt::layer().json().with_current_span(true).with_span_list(false).with_writer(file_writer))
+        .init();
+
+    LogGuard(guard)
+}
+
+#[derive(Clone)]
+pub struct RequestSpan {
+    pub id: String,
+    pub span: Span,
+}
+
+// Stand-in for requests that never passed through the fairing
+fn untracked() -> RequestSpan {
+    RequestSpan { id: String::new(), span: Span::none() }
+}
+
+pub fn request_span<'r>(req: &'r Request<'_>) -> &'r RequestSpan {
+    req.local_cache(untracked)
+}
+
+// Incoming ids are honoured when they look sane, anything else gets a fresh one
+fn request_id(req: &Request<'_>) -> String {
+    req.headers()
+        .get_one(REQUEST_ID_HEADER)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .filter(|id| id.chars().all(|c| c.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| rand::thread_rng().sample_iter(&Alphanumeric).take(20).map(char::from).collect())
+}
+
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info { name: "Request logger", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = request_id(req);
+        let span = info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            uri = %req.uri(),
+            key = field::Empty,
+        );
+        req.local_cache(|| RequestSpan { id, span });
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let request = request_span(req);
+        let route = req.route().and_then(|route| route.name.as_deref()).unwrap_or("unmatched");
+        info!(parent: &request.span, route, status = res.status().code, "request completed");
+
+        if !request.id.is_empty() {
+            res.set_header(Header::new(REQUEST_ID_HEADER, request.id.clone()));
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestSpan {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request_span(req).clone())
+    }
+}
@@ -1,73 +1,52 @@
 #[macro_use] extern crate rocket;
 
+mod accounts;
+mod announcements;
+mod audit;
+mod calendar;
+mod checkin;
+mod cors;
+mod dashboard;
+mod diagnostics;
+mod display;
+mod emergency;
+mod export;
+mod feeds;
+mod history;
+mod https;
+mod import;
+mod keys;
+mod logging;
+mod metrics;
+mod openapi;
+mod persistence;
+mod push;
+mod registration;
+mod schedule;
+mod shutdown;
+mod status_page;
+mod stream;
+mod tokens;
+mod totp;
+mod webhooks;
+
 use rocket::{serde::{json::Json, Serialize, Deserialize}};
-use chrono::{DateTime, Utc};
-use std::{fs, str::FromStr, sync::Mutex, collections::HashMap};
+use std::{str::FromStr, sync::Mutex};
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::time::Duration;
 use rocket::http::Status;
-use rocket::request::{FromRequest, Outcome, Request};
 use dotenvy::{dotenv, var};
 use rocket_governor::{Method, Quota, RocketGovernable, RocketGovernor};
-use rocket_cors::{CorsOptions};
-use rocket_cors::{AllowedOrigins, AllowedHeaders};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use keys::{ApiKey, ApiKeys};
+// Handlers that hold several state locks at once take them in this order, skipping any they don't need:
+// emergency → events → schedule → history → registrations. Other stores are only locked on their own
+// or after these.
 type SharedEvents = Mutex<Vec<EventDetail>>;
 
-pub struct ApiKey(String);
-
-struct ApiKeys {
-    root_key: String,
-    event_keys: HashMap<String, String>, // event_name -> api_key
-}
-
-impl ApiKeys {
-    fn load_from_env() -> Self {
-        dotenv().ok();
-
-        let root_key = var("API_SECRET_KEY").expect("API_SECRET_KEY not set");
-
-        let mut event_keys = HashMap::new();
-
-        if let Ok(key) = var("YUKTI_API_KEY") {
-            event_keys.insert("Yukti".to_string(), key);
-        }
-        if let Ok(key) = var("NATYA_API_KEY") {
-            event_keys.insert("Natya-Sutra".to_string(), key);
-        }
-        if let Ok(key) = var("NAADA_API_KEY") {
-            event_keys.insert("Naada-Nirvana".to_string(), key);
-        }
-        if let Ok(key) = var("NAZAKAT_API_KEY") {
-            event_keys.insert("Nazakat".to_string(), key);
-        }
-        if let Ok(key) = var("NATAKA_API_KEY") {
-            event_keys.insert("Nataka".to_string(), key);
-        }
-        // Add more events + keys as needed
-
-        ApiKeys { root_key, event_keys }
-    }
-}
-
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for ApiKey {
-    type Error = ();
-
-    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let api_keys = match req.rocket().state::<ApiKeys>() {
-            Some(state) => state,
-            None => return Outcome::Error((Status::InternalServerError, ())),
-        };
-
-        // Extract Authorization: Bearer <key>
-        let key_opt = req.headers()
-            .get_one("Authorization")
-            .and_then(|header| header.strip_prefix("Bearer "));
-
-        match key_opt {
-            Some(key) => Outcome::Success(ApiKey(key.to_string())),
-            None => Outcome::Error((Status::Unauthorized, ())),
-        }
-    }
-}
+const STATE_FILE: &str = "curr_state.json";
+const BASE_EVENTS_FILE: &str = "events.json";
 
 pub struct RateLimitGuard;
 
@@ -77,7 +56,7 @@ impl<'r> RocketGovernable<'r> for RateLimitGuard {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 enum EventStatus {
     Started,
@@ -91,13 +70,85 @@ enum EventStatus {
     Soon,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl EventStatus {
+    const ALL: [EventStatus; 9] = [
+        EventStatus::Started,
+        EventStatus::Ended,
+        EventStatus::Round1,
+        EventStatus::Round2,
+        EventStatus::Round3,
+        EventStatus::Round4,
+        EventStatus::Ongoing,
+        EventStatus::Delayed,
+        EventStatus::Soon,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventStatus::Started => "Started",
+            EventStatus::Ended => "Ended",
+            EventStatus::Round1 => "Round 1",
+            EventStatus::Round2 => "Round 2",
+            EventStatus::Round3 => "Round 3",
+            EventStatus::Round4 => "Round 4",
+            EventStatus::Ongoing => "Ongoing",
+            EventStatus::Delayed => "Delayed",
+            EventStatus::Soon => "Soon",
+        }
+    }
+
+    // Colour group used by the HTML pages
+    pub fn tone(&self) -> &'static str {
+        match self {
+            EventStatus::Started
+            | EventStatus::Ongoing
+            | EventStatus::Round1
+            | EventStatus::Round2
+            | EventStatus::Round3
+            | EventStatus::Round4 => "live",
+            EventStatus::Delayed => "delayed",
+            EventStatus::Soon => "upcoming",
+            EventStatus::Ended => "ended",
+        }
+    }
+
+    // Statuses a coordinator would normally move to next, offered as one-tap buttons.
+    // Updates to other statuses are still accepted by the API.
+    pub fn transitions(&self) -> Vec<EventStatus> {
+        use EventStatus::*;
+
+        match self {
+            Soon => vec![Started, Round1, Delayed],
+            Delayed => vec![Started, Round1, Soon],
+            Started => vec![Round1, Ongoing, Delayed, Ended],
+            Round1 => vec![Round2, Ongoing, Delayed, Ended],
+            Round2 => vec![Round3, Ongoing, Delayed, Ended],
+            Round3 => vec![Round4, Ongoing, Delayed, Ended],
+            Round4 => vec![Ongoing, Delayed, Ended],
+            Ongoing => vec![Delayed, Ended],
+            Ended => vec![Ongoing],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(crate = "rocket::serde")]
 struct EventDetail {
     name: String,
     status: EventStatus,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+struct EventSummary {
+    #[serde(flatten)]
+    detail: EventDetail,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    participation: Option<registration::ParticipationCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emergency: Option<String>,
+}
+
 impl FromStr for EventStatus {
     type Err = ();
 
@@ -117,50 +168,48 @@ impl FromStr for EventStatus {
     }
 }
 
-fn save_current_state(events: &Vec<EventDetail>) -> std::io::Result<()> {
-    let serialized = serde_json::to_string_pretty(events)?;
-    fs::write("curr_state.json", serialized)?;
-    Ok(())
-}
-
-fn load_events_from_file(path: &str) -> Vec<EventDetail> {
-    let data = fs::read_to_string(path).expect("Failed to read events.json");
-    serde_json::from_str(&data).expect("Failed to parse JSON")  
-}
-
-fn load_initial_state() -> Vec<EventDetail> {
-    if let Ok(data) = fs::read_to_string("curr_state.json") {
-        if let Ok(events) = serde_json::from_str(&data) {
-            return events;
-        }
+// Also reports which file the state came from
+fn load_initial_state() -> (Vec<EventDetail>, &'static str) {
+    if let Some(events) = persistence::load(STATE_FILE) {
+        return (events, STATE_FILE);
     }
 
-    let base_data = fs::read_to_string("events.json").expect("Failed to read events.json");
-    let events: Vec<EventDetail> = serde_json::from_str(&base_data).expect("Invalid base JSON");
+    let events: Vec<EventDetail> = persistence::load(BASE_EVENTS_FILE).expect("Failed to read events.json");
 
-    fs::write("curr_state.json", serde_json::to_string_pretty(&events).unwrap())
-        .expect("Failed to initialize curr_state.json");
+    persistence::save(STATE_FILE, &events).expect("Failed to initialize curr_state.json");
 
-    events
+    (events, BASE_EVENTS_FILE)
 }
 
+#[utoipa::path(
+    post, path = "/api/v3/update/{event_name}/{status}", tag = "events",
+    params(("event_name" = String, Path, description = "Event to update"), ("status" = String, Path, description = "New status, case-insensitive EventStatus name")),
+    responses((status = 200, description = "All events after the update", body = [EventDetail]), (status = 401, description = "Missing or unknown key"), (status = 403, description = "Key may not edit this event"), (status = 423, description = "Statuses are frozen by an emergency override")),
+    security(("bearer" = []))
+)]
 #[post("/api/v3/update/<event_name>/<status>")]
+#[allow(clippy::too_many_arguments)]
 fn update_event(
     event_name: &str,
     status: &str,
     state: &rocket::State<SharedEvents>,
     api_key: ApiKey,
     api_keys: &rocket::State<ApiKeys>,
+    updates: &rocket::State<stream::LiveUpdates>,
+    emergency: &rocket::State<emergency::SharedEmergency>,
+    history: &rocket::State<history::SharedHistory>,
+    request: logging::RequestSpan,
     _limitguard: RocketGovernor<RateLimitGuard>
 ) -> Result<Json<Vec<EventDetail>>, Status> {
 
-    // Root key can update any event
-    if api_key.0 != api_keys.root_key {
-        // Otherwise check if key matches the event's allowed key
-        match api_keys.event_keys.get(event_name) {
-            Some(expected_key) if *expected_key == api_key.0 => (),
-            _ => return Err(Status::Forbidden),
-        }
+    if !api_keys.can_edit(&api_key, event_name) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    // Statuses are frozen until the emergency is cleared so they can be restored exactly
+    let emergency = emergency.lock().unwrap();
+    if emergency.is_some() {
+        return Err(Status::Locked);
     }
 
     let mut events = state.lock().unwrap();
@@ -170,6 +219,7 @@ fn update_event(
         Err(_) => return Ok(Json(events.clone())),
     };
 
+    let before = events.clone();
     let mut updated = false;
     for event in events.iter_mut() {
         if event.name == event_name {
@@ -179,51 +229,188 @@ fn update_event(
     }
 
     if updated {
-        fs::write("curr_state.json", serde_json::to_string_pretty(&*events).unwrap())
-            .expect("Unable to write curr_state.json");
+        persistence::save(STATE_FILE, &*events).expect("Unable to write curr_state.json");
+        let changes = stream::LiveUpdate::status_changes(&before, &events, &api_keys.identity(&api_key));
+        if let Err(error) = history.lock().unwrap().record(&changes) {
+            warn!(parent: &request.span, %error, "failed to record status history");
+        }
+
+        updates.publish(stream::LiveUpdate::Events { events: events.clone() });
+        for change in changes {
+            if let stream::LiveUpdate::StatusChanged { event, from, to, .. } = &change {
+                info!(parent: &request.span, event = %event, from = ?from, to = ?to, "event status updated");
+            }
+            updates.publish(change);
+        }
     }
 
     Ok(Json(events.clone()))
 }
 
+#[utoipa::path(
+    get, path = "/api/v3/get/events", tag = "events",
+    responses((status = 200, description = "All events with their current status", body = [EventSummary]), (status = 429, description = "Rate limited"))
+)]
 #[get("/api/v3/get/events")]
 fn get_events(
     state: &rocket::State<SharedEvents>,
+    registrations: &rocket::State<registration::SharedRegistrations>,
+    emergency: &rocket::State<emergency::SharedEmergency>,
     _limitguard: RocketGovernor<RateLimitGuard>
-) -> Json<Vec<EventDetail>> {
+) -> Json<Vec<EventSummary>> {
+    let emergency_message = emergency.lock().unwrap().as_ref().map(|e| e.message.clone());
     let events = state.lock().unwrap();
-    Json(events.clone())
+    let registrations = registrations.lock().unwrap();
+
+    Json(events.iter().map(|event| EventSummary {
+        detail: event.clone(),
+        participation: registrations.counts(&event.name),
+        emergency: emergency_message.clone(),
+    }).collect())
 }
 
-#[launch]
-fn rocket() -> _ {
-    let events = load_initial_state();
-    let api_keys = ApiKeys::load_from_env();
-
-    let allowed_origins = AllowedOrigins::some_exact(&["https://adharvaa.com"]);
-
-    let cors = CorsOptions {
-        allowed_origins,
-        allowed_methods: vec!["GET".parse().unwrap(), "POST".parse().unwrap()]
-            .into_iter()
-            .collect(),
-        allowed_headers: AllowedHeaders::some(&[
-            "Authorization",
-            "Accept",
-            "Content-Type",
-            "Origin",
-        ]),
-        allow_credentials: true,
-        ..Default::default()
-    }
-    .to_cors()
-    .expect("error creating CORS fairing");
+// State is reloaded from disk, so this can be called again to relaunch. Stores that background tasks
+// keep writing to are built once by `main` and passed in, so a relaunch cannot leave a task saving
+// a stale copy over newer data
+fn rocket(webhooks: webhooks::Webhooks, push_notifier: push::PushNotifier) -> rocket::Rocket<rocket::Build> {
+    dotenv().ok();
+
+    let (events, state_source) = load_initial_state();
+    let api_keys = ApiKeys::load();
+    let accounts = accounts::Accounts::load();
+    let tokens = tokens::Tokens::load();
+    let second_factors = totp::SecondFactors::load();
+    let registrations = registration::RegistrationStore::load();
+    let checkin_signer = checkin::CheckinSigner::load_from_env();
+    let announcements = announcements::AnnouncementBoard::load();
+    let emergency = emergency::load();
+    let history = history::StatusHistory::load();
+    let displays = display::DisplayBoards::load();
+    let schedule = schedule::Schedule::load();
+    let audit_log = audit::AuditLog::load();
+    let cors = cors::CorsPolicy::load();
+
+    let mut startup_report = diagnostics::StartupReport::new(state_source);
+    if !api_keys.has_event_keys() {
+        startup_report.warn("No per-event API keys configured, only admin keys can update events");
+    }
+    if api_keys.env_ignored() {
+        startup_report.warn("API keys are loaded from keys.json, API_SECRET_KEY and the *_API_KEY variables no longer grant access");
+    }
+    if var("CHECKIN_SECRET").is_err() {
+        startup_report.warn("CHECKIN_SECRET not set, check-in tickets are signed with the root key");
+    }
+    if !second_factors.any_enrolled() {
+        startup_report.warn("No admin has enrolled a second factor, admin actions are refused until one does at /api/v3/2fa/enroll");
+    }
+    if tokens.is_ephemeral() && rocket::Config::figment().extract_inner::<rocket::config::TlsConfig>("tls").is_ok() {
+        startup_report.warn("TOKEN_SECRET not set, access tokens stop working on restart and on every TLS certificate reload, clients have to refresh");
+    } else if tokens.is_ephemeral() {
+        startup_report.warn("TOKEN_SECRET not set, access tokens stop working on restart and clients have to refresh");
+    }
+    if !push_notifier.is_enabled() {
+        startup_report.warn("VAPID_PRIVATE_KEY not set, Web Push notifications are disabled");
+    }
+    if emergency.is_some() {
+        startup_report.warn("Started with an emergency override still active");
+    }
+    for warning in startup_report.warnings() {
+        warn!("{}", warning);
+    }
+
     rocket::build()
         .manage(Mutex::new(events))
         .manage(api_keys)
+        .manage(accounts)
+        .manage(tokens)
+        .manage(second_factors)
+        .manage(Mutex::new(registrations))
+        .manage(checkin_signer)
+        .manage(Mutex::new(announcements))
+        .manage(Mutex::new(emergency))
+        .manage(Mutex::new(history))
+        .manage(Mutex::new(displays))
+        .manage(Mutex::new(schedule))
+        .manage(Mutex::new(audit_log))
+        .manage(Mutex::new(cors))
+        .manage(stream::LiveUpdates::new())
+        .manage(webhooks)
+        .manage(push_notifier)
+        .manage(startup_report)
         .mount("/", routes![
             update_event,
             get_events
         ])
-        .attach(cors)
+        .mount("/", registration::routes())
+        .mount("/", checkin::routes())
+        .mount("/", announcements::routes())
+        .mount("/", emergency::routes())
+        .mount("/", history::routes())
+        .mount("/", schedule::routes())
+        .mount("/", calendar::routes())
+        .mount("/", import::routes())
+        .mount("/", feeds::routes())
+        .mount("/", keys::routes())
+        .mount("/", accounts::routes())
+        .mount("/", tokens::routes())
+        .mount("/", totp::routes())
+        .mount("/", status_page::routes())
+        .mount("/", display::routes())
+        .mount("/", dashboard::routes())
+        .mount("/", stream::routes())
+        .mount("/", webhooks::routes())
+        .mount("/", push::routes())
+        .mount("/", export::routes())
+        .mount("/", metrics::routes())
+        .mount("/", diagnostics::routes())
+        .mount("/", cors::routes())
+        .mount("/", openapi::routes())
+        .mount("/", shutdown::routes())
+        .attach(cors::Cors)
+        .attach(logging::RequestLogger)
+        .attach(metrics::RequestMetrics)
+        .attach(audit::AuditTrail)
+        .attach(totp::StepUpHint)
+        .attach(webhooks::fairing())
+        .attach(push::fairing())
+        .attach(shutdown::GracefulShutdown::default())
+}
+
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    dotenv().ok();
+    let _log_guard = logging::init();
+
+    let https = https::HttpsConfig::load();
+    let reload = Arc::new(AtomicBool::new(false));
+    let webhooks = webhooks::Webhooks::load();
+    let push_notifier = push::PushNotifier::load();
+    let mut redirect_port = https.redirect_port;
+
+    loop {
+        let server = rocket(webhooks.clone(), push_notifier.clone())
+            .attach(https::Hsts::new(&https))
+            .attach(https::CertReloader {
+                reload: reload.clone(),
+                check_every: Duration::from_secs(https.reload_check_seconds),
+            })
+            .ignite()
+            .await?;
+
+        // The redirect server stays up across certificate reloads
+        if let Some(port) = redirect_port.take().filter(|_| server.config().tls_enabled()) {
+            let redirect = https::redirect_server(server.config(), port);
+            rocket::tokio::spawn(async move {
+                if let Err(error) = redirect.launch().await {
+                    warn!(%error, "HTTP to HTTPS redirect server stopped");
+                }
+            });
+        }
+
+        server.launch().await?;
+        if !reload.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        info!("relaunching with the renewed TLS certificate");
+    }
 }
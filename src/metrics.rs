@@ -0,0 +1,231 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Status};
+use rocket::{Data, Request, Response, Route, State};
+
+use crate::{EventStatus, SharedEvents};
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    rate_limited: IntCounterVec,
+    auth_failures: IntCounterVec,
+    persistence_writes: HistogramVec,
+    persistence_failures: IntCounterVec,
+    pub stream_subscribers: IntGauge,
+    updates_missed: IntCounterVec,
+    event_status: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("adharva".to_string()), None).expect("valid metrics prefix");
+
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &["route", "method", "status"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+            &["route", "method"],
+        )
+        .unwrap();
+        let rate_limited = IntCounterVec::new(
+            Opts::new("rate_limited_total", "Requests rejected by RateLimitGuard"),
+            &["route"],
+        )
+        .unwrap();
+        let auth_failures = IntCounterVec::new(
+            Opts::new("auth_failures_total", "Rejected authentications and refused authorizations by reason"),
+            &["reason"],
+        )
+        .unwrap();
+        let persistence_writes = HistogramVec::new(
+            HistogramOpts::new("persistence_write_duration_seconds", "Time spent writing state files")
+                .buckets(vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0]),
+            &["file"],
+        )
+        .unwrap();
+        let persistence_failures = IntCounterVec::new(
+            Opts::new("persistence_write_failures_total", "Failed state file writes"),
+            &["file"],
+        )
+        .unwrap();
+        let stream_subscribers = IntGauge::new("stream_subscribers", "Connected live update stream clients").unwrap();
+        let updates_missed = IntCounterVec::new(
+            Opts::new("live_updates_missed_total", "Live updates a consumer fell too far behind to receive"),
+            &["consumer"],
+        )
+        .unwrap();
+        let event_status = IntGaugeVec::new(
+            Opts::new(
+                "event_status",
+                "Current status per event: 0=Started 1=Ended 2=Round1 3=Round2 4=Round3 5=Round4 6=Ongoing 7=Delayed 8=Soon",
+            ),
+            &["event"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(request_duration.clone())).unwrap();
+        registry.register(Box::new(rate_limited.clone())).unwrap();
+        registry.register(Box::new(auth_failures.clone())).unwrap();
+        registry.register(Box::new(persistence_writes.clone())).unwrap();
+        registry.register(Box::new(persistence_failures.clone())).unwrap();
+        registry.register(Box::new(stream_subscribers.clone())).unwrap();
+        registry.register(Box::new(updates_missed.clone())).unwrap();
+        registry.register(Box::new(event_status.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            rate_limited,
+            auth_failures,
+            persistence_writes,
+            persistence_failures,
+            stream_subscribers,
+            updates_missed,
+            event_status,
+        }
+    }
+
+    pub fn auth_failure(&self, reason: &str) {
+        self.auth_failures.with_label_values(&[reason]).inc();
+    }
+
+    pub fn updates_missed(&self, consumer: &str, count: u64) {
+        self.updates_missed.with_label_values(&[consumer]).inc_by(count);
+    }
+
+    pub fn persistence_write(&self, file: &str, started: Instant, ok: bool) {
+        self.persistence_writes
+            .with_label_values(&[file])
+            .observe(started.elapsed().as_secs_f64());
+        if !ok {
+            self.persistence_failures.with_label_values(&[file]).inc();
+        }
+    }
+}
+
+fn status_code(status: &EventStatus) -> i64 {
+    match status {
+        EventStatus::Started => 0,
+        EventStatus::Ended => 1,
+        EventStatus::Round1 => 2,
+        EventStatus::Round2 => 3,
+        EventStatus::Round3 => 4,
+        EventStatus::Round4 => 5,
+        EventStatus::Ongoing => 6,
+        EventStatus::Delayed => 7,
+        EventStatus::Soon => 8,
+    }
+}
+
+// Holds when the request arrived, stored in the request-local cache
+struct RequestStart(Option<Instant>);
+
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info { name: "Request metrics", kind: Kind::Request | Kind::Response }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let route = req
+            .route()
+            .and_then(|route| route.name.as_deref())
+            .unwrap_or("unmatched");
+        let method = req.method().as_str();
+        let status = res.status();
+
+        METRICS
+            .requests
+            .with_label_values(&[route, method, &status.code.to_string()])
+            .inc();
+        if let Some(started) = req.local_cache(|| RequestStart(None)).0 {
+            METRICS
+                .request_duration
+                .with_label_values(&[route, method])
+                .observe(started.elapsed().as_secs_f64());
+        }
+
+        if status == Status::TooManyRequests {
+            METRICS.rate_limited.with_label_values(&[route]).inc();
+        }
+    }
+}
+
+#[utoipa::path(
+    get, path = "/metrics", tag = "operations",
+    responses((status = 200, description = "Prometheus text exposition", content_type = "text/plain", body = String))
+)]
+#[get("/metrics")]
+fn metrics(events: &State<SharedEvents>) -> Result<(ContentType, String), Status> {
+    // Event gauges are refreshed at scrape time so they always match the live state, and events
+    // removed since the last scrape drop out
+    let events = events.lock().unwrap();
+    METRICS.event_status.reset();
+    for event in events.iter() {
+        METRICS
+            .event_status
+            .with_label_values(&[&event.name])
+            .set(status_code(&event.status));
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&METRICS.registry.gather(), &mut buffer)
+        .map_err(|_| Status::InternalServerError)?;
+    let body = String::from_utf8(buffer).map_err(|_| Status::InternalServerError)?;
+
+    Ok((ContentType::new("text", "plain").with_params(("version", "0.0.4")), body))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![metrics]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use rocket::local::blocking::Client;
+
+    use super::*;
+    use crate::EventDetail;
+
+    fn event(name: &str, status: EventStatus) -> EventDetail {
+        EventDetail { name: name.to_string(), status }
+    }
+
+    #[test]
+    fn removed_events_drop_out_of_the_status_gauge() {
+        let events = vec![event("Metrics-Kept", EventStatus::Started), event("Metrics-Removed", EventStatus::Soon)];
+        let rocket = rocket::build().manage(Mutex::new(events)).mount("/", routes![metrics]);
+        let client = Client::untracked(rocket).unwrap();
+
+        let scrape = || client.get("/metrics").dispatch().into_string().unwrap();
+        assert!(scrape().contains("adharva_event_status{event=\"Metrics-Removed\"} 8"));
+
+        client.rocket().state::<SharedEvents>().unwrap().lock().unwrap().retain(|e| e.name != "Metrics-Removed");
+        let body = scrape();
+        assert!(body.contains("adharva_event_status{event=\"Metrics-Kept\"} 0"));
+        assert!(!body.contains("Metrics-Removed"));
+    }
+}
@@ -0,0 +1,168 @@
+use rocket::http::{ContentType, Status};
+use rocket::response::content::RawHtml;
+use rocket::Route;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Adharva Event Server",
+        description = "Live status, registration and check-in API for the Adharva fest."
+    ),
+    paths(
+        crate::get_events,
+        crate::update_event,
+        crate::stream::stream,
+        crate::history::history,
+        crate::feeds::fest_atom,
+        crate::feeds::fest_rss,
+        crate::feeds::event_feed,
+        crate::dashboard::editable_events,
+        crate::keys::whoami,
+        crate::keys::list_keys,
+        crate::keys::issue_key,
+        crate::keys::rotate_key,
+        crate::keys::revoke_key,
+        crate::tokens::exchange_key,
+        crate::tokens::refresh,
+        crate::tokens::revoke,
+        crate::totp::status,
+        crate::totp::enroll,
+        crate::totp::enroll_qr,
+        crate::totp::confirm,
+        crate::totp::verify,
+        crate::totp::regenerate_backup_codes,
+        crate::totp::remove,
+        crate::accounts::login,
+        crate::accounts::logout,
+        crate::accounts::current_session,
+        crate::accounts::list_own_sessions,
+        crate::accounts::list_sessions,
+        crate::accounts::end_session,
+        crate::accounts::change_password,
+        crate::accounts::list_users,
+        crate::accounts::create_user,
+        crate::accounts::reset_password,
+        crate::accounts::delete_user,
+        crate::schedule::get_schedule,
+        crate::schedule::set_schedule,
+        crate::schedule::delete_schedule,
+        crate::import::import_lineup,
+        crate::calendar::fest_calendar,
+        crate::calendar::category_calendar,
+        crate::calendar::event_calendar,
+        crate::registration::register,
+        crate::registration::registration_status,
+        crate::registration::cancel_registration,
+        crate::registration::configure_registration,
+        crate::registration::export_registrations,
+        crate::checkin::tickets,
+        crate::checkin::ticket_qr,
+        crate::checkin::checkin,
+        crate::checkin::advance,
+        crate::announcements::list_announcements,
+        crate::announcements::create_announcement,
+        crate::announcements::delete_announcement,
+        crate::display::display_feed,
+        crate::display::list_displays,
+        crate::display::create_display,
+        crate::display::delete_display,
+        crate::emergency::current_emergency,
+        crate::emergency::declare_emergency,
+        crate::emergency::clear_emergency,
+        crate::webhooks::list_webhooks,
+        crate::webhooks::create_webhook,
+        crate::webhooks::delete_webhook,
+        crate::webhooks::test_webhook,
+        crate::webhooks::dead_letters,
+        crate::webhooks::clear_dead_letters,
+        crate::push::vapid_public_key,
+        crate::push::subscribe,
+        crate::push::unsubscribe,
+        crate::diagnostics::healthz,
+        crate::diagnostics::readyz,
+        crate::diagnostics::diagnostics,
+        crate::export::export,
+        crate::cors::get_cors,
+        crate::cors::add_origin,
+        crate::cors::remove_origin,
+        crate::metrics::metrics,
+        openapi_json,
+    ),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "events", description = "Event statuses and the live update stream"),
+        (name = "keys", description = "API key identities, lifecycle and access tokens"),
+        (name = "accounts", description = "Coordinator accounts and browser sessions"),
+        (name = "two-factor", description = "TOTP second factor required for admin actions"),
+        (name = "schedule", description = "Schedule metadata and iCalendar feeds"),
+        (name = "registration", description = "Participant and team registration"),
+        (name = "check-in", description = "QR check-in and round progression"),
+        (name = "announcements", description = "Notice board"),
+        (name = "display", description = "Read-only venue display boards"),
+        (name = "emergency", description = "Site-wide emergency override"),
+        (name = "webhooks", description = "Outgoing webhook subscriptions"),
+        (name = "push", description = "Browser Web Push subscriptions"),
+        (name = "operations", description = "Health, readiness, diagnostics, metrics and exports"),
+    )
+)]
+struct ApiDoc;
+
+// Documents the `Authorization: Bearer <key>` scheme and the session cookie checked by the ApiKey guard
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An API key, or an access token from /api/v3/token, with an event scope for that event. Admin routes need the admin scope and an unused TOTP or backup code in the X-OTP header, or an access token stepped up at /api/v3/2fa/verify.",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                crate::accounts::SESSION_COOKIE,
+                "Set by /api/v3/login. Requests other than GET also need the X-CSRF-Token header from the login response, admin routes a session stepped up at /api/v3/2fa/verify.",
+            ))),
+        );
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/openapi.json", tag = "operations",
+    responses((status = 200, description = "This OpenAPI document", content_type = "application/json", body = String))
+)]
+#[get("/api/v3/openapi.json")]
+fn openapi_json() -> Result<(ContentType, String), Status> {
+    let document = ApiDoc::openapi().to_pretty_json().map_err(|_| Status::InternalServerError)?;
+    Ok((ContentType::JSON, document))
+}
+
+// Swagger UI is vendored under static/docs (see the LICENSE and NOTICE there) so the docs page
+// neither depends on nor trusts a CDN
+#[get("/api/v3/docs")]
+fn docs() -> RawHtml<&'static str> {
+    RawHtml(include_str!("../static/docs/index.html"))
+}
+
+#[get("/api/v3/docs/swagger-ui-bundle.js")]
+fn docs_script() -> (ContentType, &'static str) {
+    (ContentType::JavaScript, include_str!("../static/docs/swagger-ui-bundle.js"))
+}
+
+#[get("/api/v3/docs/swagger-ui.css")]
+fn docs_stylesheet() -> (ContentType, &'static str) {
+    (ContentType::CSS, include_str!("../static/docs/swagger-ui.css"))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![openapi_json, docs, docs_script, docs_stylesheet]
+}
@@ -0,0 +1,56 @@
+use rocket::serde::{de::DeserializeOwned, Serialize};
+use chrono::{DateTime, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::metrics::METRICS;
+
+static LAST_WRITE: Mutex<Option<DateTime<Utc>>> = Mutex::new(None);
+
+pub fn last_write() -> Option<DateTime<Utc>> {
+    *LAST_WRITE.lock().unwrap()
+}
+
+pub fn load<T: DeserializeOwned>(path: &str) -> Option<T> {
+    let data = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+pub fn save<T: Serialize + ?Sized>(path: &str, value: &T) -> std::io::Result<()> {
+    let started = Instant::now();
+    let result = serde_json::to_string_pretty(value)
+        .map_err(std::io::Error::from)
+        .and_then(|serialized| fs::write(path, serialized));
+
+    METRICS.persistence_write(path, started, result.is_ok());
+    if result.is_ok() {
+        *LAST_WRITE.lock().unwrap() = Some(Utc::now());
+    }
+    result
+}
+
+// One JSON document per line, for logs that would be too costly to rewrite on every change. Lines that
+// fail to parse, such as one cut short by a crash, are skipped
+pub fn load_lines<T: DeserializeOwned>(path: &str) -> Vec<T> {
+    fs::read_to_string(path)
+        .map(|data| data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+        .unwrap_or_default()
+}
+
+// Returns the number of bytes appended
+pub fn append_line<T: Serialize>(path: &str, value: &T) -> std::io::Result<u64> {
+    let started = Instant::now();
+    let result = serde_json::to_string(value).map_err(std::io::Error::from).and_then(|mut line| {
+        line.push('\n');
+        OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())?;
+        Ok(line.len() as u64)
+    });
+
+    METRICS.persistence_write(path, started, result.is_ok());
+    if result.is_ok() {
+        *LAST_WRITE.lock().unwrap() = Some(Utc::now());
+    }
+    result
+}
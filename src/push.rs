@@ -0,0 +1,543 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use dotenvy::var;
+use hkdf::Hkdf;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{ecdh::EphemeralSecret, PublicKey, SecretKey};
+use rand::{rngs::OsRng, RngCore};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::tokio::{self, sync::broadcast::{error::RecvError, Receiver}};
+use rocket::{Route, State};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use rocket_governor::RocketGovernor;
+use sha2::Sha256;
+use tracing::{error, info, warn};
+use url::Host;
+use utoipa::ToSchema;
+
+use crate::metrics::METRICS;
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{persistence, RateLimitGuard, SharedEvents};
+
+const PUSH_SUBSCRIPTIONS_FILE: &str = "push_subscriptions.json";
+const RECORD_SIZE: u32 = 4096;
+const PUSH_TTL_SECS: u32 = 60 * 60;
+const VAPID_TOKEN_LIFETIME_SECS: i64 = 12 * 60 * 60;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+impl SubscriptionKeys {
+    // Browsers send an uncompressed P-256 point and a 16 byte auth secret
+    fn is_valid(&self) -> bool {
+        let decode = |value: &str| URL_SAFE_NO_PAD.decode(value.trim_end_matches('=')).ok();
+        decode(&self.p256dh).is_some_and(|bytes| PublicKey::from_sec1_bytes(&bytes).is_ok())
+            && decode(&self.auth).is_some_and(|bytes| bytes.len() == 16)
+    }
+}
+
+// Mirrors the browser's PushSubscription.toJSON() shape
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "camelCase")]
+pub struct BrowserSubscription {
+    pub endpoint: String,
+    pub expiration_time: Option<i64>, // milliseconds since the epoch
+    pub keys: SubscriptionKeys,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PushSubscription {
+    pub subscription: BrowserSubscription,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PushSubscription {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.subscription
+            .expiration_time
+            .is_some_and(|expiry| expiry <= now.timestamp_millis())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PushStore {
+    subscriptions: Vec<PushSubscription>,
+}
+
+impl PushStore {
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(PUSH_SUBSCRIPTIONS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    fn remove_endpoint(&mut self, endpoint: &str) {
+        self.subscriptions.retain(|s| s.subscription.endpoint != endpoint);
+    }
+}
+
+// Anyone can subscribe, so the server must never be talked into posting to itself or the
+// network it sits in
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT
+                || (a == 198 && (18..20).contains(&b))) // benchmarking
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || ip.segments()[..2] == [0x2001, 0x0db8])
+            }
+        },
+    }
+}
+
+// Push services are public HTTPS endpoints, anything else is refused before it is stored
+fn is_valid_endpoint(endpoint: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(endpoint) else {
+        return false;
+    };
+    if url.scheme() != "https" || !url.username().is_empty() || url.password().is_some() {
+        return false;
+    }
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        None => false,
+    }
+}
+
+// The system resolver minus private addresses, so a public name that points inward, or starts
+// to after subscribing, is refused when delivering too
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+struct Vapid {
+    signing_key: SigningKey,
+    public_key: String, // uncompressed point, base64url
+    subject: String,
+}
+
+impl Vapid {
+    // VAPID_PRIVATE_KEY is the raw 32 byte P-256 scalar in base64url
+    fn load_from_env() -> Option<Self> {
+        let encoded = var("VAPID_PRIVATE_KEY").ok()?;
+        let bytes = URL_SAFE_NO_PAD.decode(encoded.trim()).expect("VAPID_PRIVATE_KEY is not valid base64url");
+        let secret = SecretKey::from_slice(&bytes).expect("VAPID_PRIVATE_KEY is not a valid P-256 key");
+        let public_key = URL_SAFE_NO_PAD.encode(secret.public_key().to_encoded_point(false).as_bytes());
+        let subject = var("VAPID_SUBJECT").unwrap_or_else(|_| "mailto:admin@adharvaa.com".to_string());
+
+        Some(Vapid { signing_key: SigningKey::from(secret), public_key, subject })
+    }
+
+    fn authorization(&self, audience: &str) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::json!({
+            "aud": audience,
+            "exp": Utc::now().timestamp() + VAPID_TOKEN_LIFETIME_SECS,
+            "sub": self.subject,
+        });
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        let token = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes()));
+
+        format!("vapid t={}, k={}", token, self.public_key)
+    }
+}
+
+fn hkdf_expand(salt: &[u8], ikm: &[u8], info: &[u8], out: &mut [u8]) {
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, out)
+        .expect("HKDF output length is within limits");
+}
+
+// Single-record "aes128gcm" content encoding from RFC 8188 with the Web Push key derivation of RFC 8291
+fn encrypt(keys: &SubscriptionKeys, payload: &[u8]) -> Option<Vec<u8>> {
+    let ua_public_bytes = URL_SAFE_NO_PAD.decode(keys.p256dh.trim_end_matches('=')).ok()?;
+    let auth_secret = URL_SAFE_NO_PAD.decode(keys.auth.trim_end_matches('=')).ok()?;
+    let ua_public = PublicKey::from_sec1_bytes(&ua_public_bytes).ok()?;
+
+    let as_secret = EphemeralSecret::random(&mut OsRng);
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared = as_secret.diffie_hellman(&ua_public);
+
+    let mut key_info = b"WebPush: info\0".to_vec();
+    key_info.extend_from_slice(&ua_public_bytes);
+    key_info.extend_from_slice(as_public.as_bytes());
+    let mut ikm = [0u8; 32];
+    hkdf_expand(&auth_secret, shared.raw_secret_bytes(), &key_info, &mut ikm);
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    hkdf_expand(&salt, &ikm, b"Content-Encoding: aes128gcm\0", &mut cek);
+    hkdf_expand(&salt, &ikm, b"Content-Encoding: nonce\0", &mut nonce);
+
+    // 0x02 marks the final (and only) record
+    let mut plaintext = payload.to_vec();
+    plaintext.push(0x02);
+    let ciphertext = Aes128Gcm::new_from_slice(&cek).ok()?.encrypt(Nonce::from_slice(&nonce), plaintext.as_slice()).ok()?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+    Some(body)
+}
+
+enum DeliveryOutcome {
+    Delivered,
+    Gone,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct PushNotifier {
+    store: Arc<Mutex<PushStore>>,
+    vapid: Option<Arc<Vapid>>,
+    endpoint_override: Option<reqwest::Url>,
+    client: reqwest::Client,
+}
+
+impl PushNotifier {
+    pub fn load() -> Self {
+        let mut store: PushStore = persistence::load(PUSH_SUBSCRIPTIONS_FILE).unwrap_or_default();
+        store.subscriptions.retain(|s| is_valid_endpoint(&s.subscription.endpoint));
+        let endpoint_override = var("PUSH_SERVICE_URL")
+            .ok()
+            .map(|url| reqwest::Url::parse(&url).expect("PUSH_SERVICE_URL is not a valid URL"));
+
+        // PUSH_SERVICE_URL is set by the operator and may well be a local test service
+        let mut client = reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).redirect(Policy::none());
+        if endpoint_override.is_none() {
+            client = client.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = client.build().expect("Failed to build push HTTP client");
+
+        PushNotifier {
+            store: Arc::new(Mutex::new(store)),
+            vapid: Vapid::load_from_env().map(Arc::new),
+            endpoint_override,
+            client,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.vapid.is_some()
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(PUSH_SUBSCRIPTIONS_FILE, &*self.store.lock().unwrap())
+    }
+
+    // Redirects deliveries to PUSH_SERVICE_URL when set, keeping the subscription's path
+    fn delivery_url(&self, endpoint: &str) -> Option<reqwest::Url> {
+        let mut url = reqwest::Url::parse(endpoint).ok()?;
+        if let Some(base) = &self.endpoint_override {
+            url.set_scheme(base.scheme()).ok()?;
+            url.set_host(base.host_str()).ok()?;
+            url.set_port(base.port()).ok()?;
+        }
+        Some(url)
+    }
+
+    async fn deliver(&self, subscription: &BrowserSubscription, payload: &[u8]) -> DeliveryOutcome {
+        let (vapid, url) = match (&self.vapid, self.delivery_url(&subscription.endpoint)) {
+            (Some(vapid), Some(url)) => (vapid, url),
+            _ => return DeliveryOutcome::Failed,
+        };
+        // Keys are checked on subscribe, so this is our fault rather than a dead subscription
+        let body = match encrypt(&subscription.keys, payload) {
+            Some(body) => body,
+            None => {
+                error!("could not encrypt push notification");
+                return DeliveryOutcome::Failed;
+            }
+        };
+        let audience = url.origin().ascii_serialization();
+
+        let response = self
+            .client
+            .post(url)
+            .header("Authorization", vapid.authorization(&audience))
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("TTL", PUSH_TTL_SECS.to_string())
+            .header("Urgency", "high")
+            .body(body)
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => DeliveryOutcome::Delivered,
+            // The push service no longer knows this subscription
+            Ok(response) if response.status() == 404 || response.status() == 410 => DeliveryOutcome::Gone,
+            Ok(response) => {
+                warn!(status = response.status().as_u16(), "push service rejected notification");
+                DeliveryOutcome::Failed
+            }
+            Err(error) => {
+                warn!(%error, "push delivery failed");
+                DeliveryOutcome::Failed
+            }
+        }
+    }
+
+    async fn notify(self, event: String, payload: Vec<u8>) {
+        let now = Utc::now();
+        let targets: Vec<BrowserSubscription> = {
+            let mut store = self.store.lock().unwrap();
+            let before = store.subscriptions.len();
+            store.subscriptions.retain(|s| !s.is_expired(now));
+            if store.subscriptions.len() != before {
+                let _ = store.save();
+            }
+            store
+                .subscriptions
+                .iter()
+                .filter(|s| s.events.contains(&event))
+                .map(|s| s.subscription.clone())
+                .collect()
+        };
+
+        let mut gone = Vec::new();
+        for subscription in targets {
+            if let DeliveryOutcome::Gone = self.deliver(&subscription, &payload).await {
+                gone.push(subscription.endpoint);
+            }
+        }
+
+        if !gone.is_empty() {
+            info!(count = gone.len(), "removing expired push subscriptions");
+            let mut store = self.store.lock().unwrap();
+            for endpoint in &gone {
+                store.remove_endpoint(endpoint);
+            }
+            let _ = store.save();
+        }
+    }
+
+    async fn dispatch(self, mut rx: Receiver<LiveUpdate>) {
+        loop {
+            let (event, to, body) = match rx.recv().await {
+                Ok(LiveUpdate::StatusChanged { event, to, .. }) => {
+                    let body = format!("{} is now {:?}", event, to);
+                    (event, to, body)
+                }
+                // Nobody can have subscribed to an event that was just added, so only removals are sent
+                Ok(LiveUpdate::EventRemoved { event, status, .. }) => {
+                    let body = format!("{} was removed from the lineup", event);
+                    (event, status, body)
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(missed, "push dispatcher fell behind, notifications were not sent");
+                    METRICS.updates_missed("push", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let payload = serde_json::json!({
+                "title": event,
+                "body": body,
+                "event": event,
+                "status": to,
+            });
+            tokio::spawn(self.clone().notify(event, payload.to_string().into_bytes()));
+        }
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Web Push notifier", |rocket| Box::pin(async move {
+        let notifier = rocket.state::<PushNotifier>().expect("PushNotifier not managed").clone();
+        if notifier.vapid.is_none() {
+            return;
+        }
+        let rx = rocket.state::<LiveUpdates>().expect("LiveUpdates not managed").subscribe();
+        tokio::spawn(notifier.dispatch(rx));
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SubscribeRequest {
+    subscription: BrowserSubscription,
+    events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct UnsubscribeRequest {
+    endpoint: String,
+    #[serde(default)]
+    events: Vec<String>, // empty drops the subscription entirely
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SubscriptionStatus {
+    endpoint: String,
+    events: Vec<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/push/vapid-public-key", tag = "push",
+    responses((status = 200, description = "Application server key for PushManager.subscribe()", body = String), (status = 503, description = "Web Push is not configured"))
+)]
+#[get("/api/v3/push/vapid-public-key")]
+fn vapid_public_key(notifier: &State<PushNotifier>) -> Result<String, Status> {
+    notifier.vapid.as_ref().map(|v| v.public_key.clone()).ok_or(Status::ServiceUnavailable)
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/push/subscribe", tag = "push", request_body = SubscribeRequest,
+    responses((status = 200, body = SubscriptionStatus), (status = 404), (status = 422, description = "No events, malformed keys, or an endpoint that is not a public https URL"), (status = 503))
+)]
+#[post("/api/v3/push/subscribe", data = "<request>")]
+fn subscribe(
+    request: Json<SubscribeRequest>,
+    notifier: &State<PushNotifier>,
+    events: &State<SharedEvents>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<SubscriptionStatus>, Status> {
+    if notifier.vapid.is_none() {
+        return Err(Status::ServiceUnavailable);
+    }
+
+    let request = request.into_inner();
+    if request.events.is_empty()
+        || !is_valid_endpoint(&request.subscription.endpoint)
+        || !request.subscription.keys.is_valid()
+        || notifier.delivery_url(&request.subscription.endpoint).is_none()
+    {
+        return Err(Status::UnprocessableEntity);
+    }
+    {
+        let events = events.lock().unwrap();
+        if !request.events.iter().all(|name| events.iter().any(|e| e.name == *name)) {
+            return Err(Status::NotFound);
+        }
+    }
+
+    let mut store = notifier.store.lock().unwrap();
+    let existing = store
+        .subscriptions
+        .iter_mut()
+        .find(|s| s.subscription.endpoint == request.subscription.endpoint);
+
+    // Browsers reuse one endpoint per origin, so extra events are merged into it
+    let subscription = match existing {
+        Some(existing) => {
+            existing.subscription = request.subscription;
+            for event in request.events {
+                if !existing.events.contains(&event) {
+                    existing.events.push(event);
+                }
+            }
+            existing.clone()
+        }
+        None => {
+            let created = PushSubscription {
+                subscription: request.subscription,
+                events: request.events,
+                created_at: Utc::now(),
+            };
+            store.subscriptions.push(created.clone());
+            created
+        }
+    };
+    store.save()?;
+
+    Ok(Json(SubscriptionStatus {
+        endpoint: subscription.subscription.endpoint,
+        events: subscription.events,
+    }))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/push/unsubscribe", tag = "push", request_body = UnsubscribeRequest,
+    responses((status = 204), (status = 404))
+)]
+#[post("/api/v3/push/unsubscribe", data = "<request>")]
+fn unsubscribe(
+    request: Json<UnsubscribeRequest>,
+    notifier: &State<PushNotifier>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Status, Status> {
+    let request = request.into_inner();
+    let mut store = notifier.store.lock().unwrap();
+
+    let subscription = store
+        .subscriptions
+        .iter_mut()
+        .find(|s| s.subscription.endpoint == request.endpoint)
+        .ok_or(Status::NotFound)?;
+
+    subscription.events.retain(|e| !request.events.is_empty() && !request.events.contains(e));
+    if subscription.events.is_empty() {
+        store.remove_endpoint(&request.endpoint);
+    }
+    store.save()?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![vapid_public_key, subscribe, unsubscribe]
+}
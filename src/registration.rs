@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use utoipa::ToSchema;
+
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{persistence, ApiKey, ApiKeys, RateLimitGuard, SharedEvents};
+
+const REGISTRATIONS_FILE: &str = "registrations.json";
+
+pub type SharedRegistrations = Mutex<RegistrationStore>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RegistrationConfig {
+    pub capacity: usize,
+    pub min_team_size: usize,
+    pub max_team_size: usize,
+    #[serde(default = "default_open")]
+    pub open: bool,
+}
+
+fn default_open() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Participant {
+    pub name: String,
+    pub email: String,
+    pub phone: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub enum RegistrationState {
+    Confirmed,
+    Waitlisted,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Registration {
+    pub id: String,
+    pub team_name: Option<String>,
+    pub members: Vec<Participant>,
+    pub state: RegistrationState,
+    pub registered_at: DateTime<Utc>,
+    #[serde(default = "first_round")]
+    pub round: u8, // highest round this team has advanced to
+    #[serde(default)]
+    pub attendance: Vec<Attendance>,
+}
+
+fn first_round() -> u8 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Attendance {
+    pub member: usize,
+    pub round: u8,
+    pub checked_in_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ParticipationCounts {
+    pub registered: usize,
+    pub checked_in: usize,
+    pub still_in: usize,
+}
+
+// What happened to an event's registrations, sent out with its new counts
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum RegistrationChange {
+    Registered,
+    Cancelled,
+    Configured,
+    CheckedIn,
+    Advanced,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct EventRegistrations {
+    pub config: RegistrationConfig,
+    #[serde(default)]
+    pub entries: Vec<Registration>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RegistrationStore {
+    events: HashMap<String, EventRegistrations>, // event_name -> registrations
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RegistrationRequest {
+    team_name: Option<String>,
+    members: Vec<Participant>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RegistrationReceipt {
+    id: String,
+    event: String,
+    state: RegistrationState,
+    waitlist_position: Option<usize>,
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn new_registration_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+impl EventRegistrations {
+    fn confirmed_count(&self) -> usize {
+        self.entries.iter().filter(|r| r.state == RegistrationState::Confirmed).count()
+    }
+
+    fn waitlist_position(&self, id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .filter(|r| r.state == RegistrationState::Waitlisted)
+            .position(|r| r.id == id)
+            .map(|pos| pos + 1)
+    }
+
+    fn is_duplicate(&self, participant: &Participant) -> bool {
+        let email = normalize_email(&participant.email);
+        let phone = normalize_phone(&participant.phone);
+
+        self.entries
+            .iter()
+            .filter(|r| r.state != RegistrationState::Cancelled)
+            .flat_map(|r| r.members.iter())
+            .any(|m| normalize_email(&m.email) == email || normalize_phone(&m.phone) == phone)
+    }
+
+    // Fill any free seats from the front of the waitlist
+    fn promote_waitlist(&mut self) {
+        let mut free = self.config.capacity.saturating_sub(self.confirmed_count());
+        for entry in self.entries.iter_mut() {
+            if free == 0 {
+                break;
+            }
+            if entry.state == RegistrationState::Waitlisted {
+                entry.state = RegistrationState::Confirmed;
+                free -= 1;
+            }
+        }
+    }
+
+    // "Still in" means reaching the furthest round any team has advanced to
+    fn counts(&self) -> ParticipationCounts {
+        let confirmed = || self.entries.iter().filter(|r| r.state == RegistrationState::Confirmed);
+        let current_round = confirmed().map(|r| r.round).max().unwrap_or(1);
+
+        ParticipationCounts {
+            registered: confirmed().count(),
+            checked_in: confirmed()
+                .filter(|r| r.attendance.iter().any(|a| a.round == current_round))
+                .count(),
+            still_in: confirmed().filter(|r| r.round == current_round).count(),
+        }
+    }
+
+    fn receipt(&self, event_name: &str, registration: &Registration) -> RegistrationReceipt {
+        RegistrationReceipt {
+            id: registration.id.clone(),
+            event: event_name.to_string(),
+            state: registration.state,
+            waitlist_position: self.waitlist_position(&registration.id),
+        }
+    }
+}
+
+impl RegistrationStore {
+    pub fn load() -> Self {
+        persistence::load(REGISTRATIONS_FILE).unwrap_or_default()
+    }
+
+    pub fn event(&self, event_name: &str) -> Option<&EventRegistrations> {
+        self.events.get(event_name)
+    }
+
+    pub fn event_mut(&mut self, event_name: &str) -> Option<&mut EventRegistrations> {
+        self.events.get_mut(event_name)
+    }
+
+    // Drops registrations, attendance included, of events that no longer exist. Returns how many events had any
+    pub fn remove_events(&mut self, event_names: &[&str]) -> usize {
+        let before = self.events.len();
+        self.events.retain(|name, _| !event_names.contains(&name.as_str()));
+        before - self.events.len()
+    }
+
+    pub fn counts(&self, event_name: &str) -> Option<ParticipationCounts> {
+        self.event(event_name).map(EventRegistrations::counts)
+    }
+
+    // Only the event and its counts go out, never who registered
+    pub fn publish(&self, updates: &LiveUpdates, event_name: &str, change: RegistrationChange) {
+        if let Some(counts) = self.counts(event_name) {
+            updates.publish(LiveUpdate::RegistrationsChanged { event: event_name.to_string(), change, counts });
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Status> {
+        persistence::save(REGISTRATIONS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(REGISTRATIONS_FILE, self)
+    }
+
+    // Swaps in one event's registrations, keeping the old ones if they can't be written
+    fn replace_event(&mut self, event_name: &str, registrations: EventRegistrations) -> Result<(), Status> {
+        let previous = self.events.insert(event_name.to_string(), registrations);
+        self.save().inspect_err(|_| match previous {
+            Some(previous) => {
+                self.events.insert(event_name.to_string(), previous);
+            }
+            None => {
+                self.events.remove(event_name);
+            }
+        })
+    }
+}
+
+fn event_exists(events: &SharedEvents, event_name: &str) -> bool {
+    events.lock().unwrap().iter().any(|e| e.name == event_name)
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/register/{event_name}", tag = "registration",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")), request_body = RegistrationRequest,
+    responses((status = 200, body = RegistrationReceipt), (status = 404, description = "Unknown event or registration closed"), (status = 409, description = "Participant already registered"), (status = 422, description = "Team size out of bounds or missing contact details"))
+)]
+#[post("/api/v3/register/<event_name>", data = "<request>")]
+fn register(
+    event_name: &str,
+    request: Json<RegistrationRequest>,
+    events: &State<SharedEvents>,
+    store: &State<SharedRegistrations>,
+    updates: &State<LiveUpdates>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<RegistrationReceipt>, Status> {
+    if !event_exists(events, event_name) {
+        return Err(Status::NotFound);
+    }
+
+    let mut store = store.lock().unwrap();
+    let mut event = match store.event(event_name) {
+        Some(event) if event.config.open => event.clone(),
+        _ => return Err(Status::NotFound),
+    };
+
+    let request = request.into_inner();
+    let team_size = request.members.len();
+    if team_size < event.config.min_team_size || team_size > event.config.max_team_size {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    for (i, member) in request.members.iter().enumerate() {
+        let email = normalize_email(&member.email);
+        let phone = normalize_phone(&member.phone);
+        if email.is_empty() || phone.is_empty() {
+            return Err(Status::UnprocessableEntity);
+        }
+
+        // Also reject the same person appearing twice within one team
+        let repeated = request.members[..i]
+            .iter()
+            .any(|m| normalize_email(&m.email) == email || normalize_phone(&m.phone) == phone);
+        if repeated || event.is_duplicate(member) {
+            return Err(Status::Conflict);
+        }
+    }
+
+    let state = if event.confirmed_count() < event.config.capacity {
+        RegistrationState::Confirmed
+    } else {
+        RegistrationState::Waitlisted
+    };
+
+    let registration = Registration {
+        id: new_registration_id(),
+        team_name: request.team_name,
+        members: request.members,
+        state,
+        registered_at: Utc::now(),
+        round: first_round(),
+        attendance: Vec::new(),
+    };
+    event.entries.push(registration.clone());
+    let receipt = event.receipt(event_name, &registration);
+
+    store.replace_event(event_name, event)?;
+    store.publish(updates, event_name, RegistrationChange::Registered);
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/register/{event_name}/{id}", tag = "registration",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events"), ("id" = String, Path, description = "Registration id from the receipt")),
+    responses((status = 200, body = RegistrationReceipt), (status = 404))
+)]
+#[get("/api/v3/register/<event_name>/<id>")]
+fn registration_status(
+    event_name: &str,
+    id: &str,
+    store: &State<SharedRegistrations>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<RegistrationReceipt>, Status> {
+    let store = store.lock().unwrap();
+    let event = store.event(event_name).ok_or(Status::NotFound)?;
+    let registration = event.entries.iter().find(|r| r.id == id).ok_or(Status::NotFound)?;
+
+    Ok(Json(event.receipt(event_name, registration)))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/register/{event_name}/{id}/cancel", tag = "registration",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events"), ("id" = String, Path, description = "Registration id from the receipt")),
+    responses((status = 200, body = RegistrationReceipt), (status = 404))
+)]
+#[post("/api/v3/register/<event_name>/<id>/cancel")]
+fn cancel_registration(
+    event_name: &str,
+    id: &str,
+    store: &State<SharedRegistrations>,
+    updates: &State<LiveUpdates>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<RegistrationReceipt>, Status> {
+    let mut store = store.lock().unwrap();
+    let mut event = store.event(event_name).cloned().ok_or(Status::NotFound)?;
+
+    let registration = event.entries.iter_mut().find(|r| r.id == id).ok_or(Status::NotFound)?;
+    registration.state = RegistrationState::Cancelled;
+    let cancelled = registration.clone();
+
+    event.promote_waitlist();
+    let receipt = event.receipt(event_name, &cancelled);
+
+    store.replace_event(event_name, event)?;
+    store.publish(updates, event_name, RegistrationChange::Cancelled);
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/registrations/{event_name}/config", tag = "registration",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")), request_body = RegistrationConfig,
+    responses((status = 200, body = RegistrationConfig), (status = 403), (status = 404), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/registrations/<event_name>/config", data = "<config>")]
+fn configure_registration(
+    event_name: &str,
+    config: Json<RegistrationConfig>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>,
+    store: &State<SharedRegistrations>,
+    updates: &State<LiveUpdates>
+) -> Result<Json<RegistrationConfig>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    if !event_exists(events, event_name) {
+        return Err(Status::NotFound);
+    }
+
+    let config = config.into_inner();
+    if config.min_team_size == 0 || config.min_team_size > config.max_team_size {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let mut store = store.lock().unwrap();
+    let mut event = store
+        .event(event_name)
+        .cloned()
+        .unwrap_or_else(|| EventRegistrations { config: config.clone(), entries: Vec::new() });
+    event.config = config.clone();
+
+    // A raised capacity lets waitlisted teams in straight away
+    event.promote_waitlist();
+
+    store.replace_event(event_name, event)?;
+    store.publish(updates, event_name, RegistrationChange::Configured);
+    Ok(Json(config))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/registrations/{event_name}", tag = "registration",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")),
+    responses((status = 200, body = EventRegistrations), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/registrations/<event_name>")]
+fn export_registrations(
+    event_name: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    store: &State<SharedRegistrations>
+) -> Result<Json<EventRegistrations>, Status> {
+    if !api_keys.can_edit(&api_key, event_name) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let store = store.lock().unwrap();
+    store.event(event_name).cloned().map(Json).ok_or(Status::NotFound)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        register,
+        registration_status,
+        cancel_registration,
+        configure_registration,
+        export_registrations
+    ]
+}
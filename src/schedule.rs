@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use utoipa::ToSchema;
+
+use crate::{persistence, ApiKey, ApiKeys, SharedEvents};
+
+const SCHEDULE_FILE: &str = "schedule.json";
+
+pub type SharedSchedule = Mutex<Schedule>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ScheduleEntry {
+    pub category: Option<String>,
+    pub venue: Option<String>,
+    pub description: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    #[serde(default)]
+    pub cancelled: bool,
+    pub delayed_until: Option<DateTime<Utc>>, // expected new start while the event is Delayed
+    #[serde(default)]
+    pub revision: u32, // bumped on every edit so calendar clients pick up the change
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Schedule {
+    entries: BTreeMap<String, ScheduleEntry>, // event_name -> entry
+}
+
+impl Schedule {
+    pub fn load() -> Self {
+        persistence::load(SCHEDULE_FILE).unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(SCHEDULE_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(SCHEDULE_FILE, self)
+    }
+
+    pub fn entry(&self, event_name: &str) -> Option<&ScheduleEntry> {
+        self.entries.get(event_name)
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, ScheduleEntry> {
+        &self.entries
+    }
+
+    // Swaps in a whole new schedule, keeping the old one if it can't be written
+    pub fn replace(&mut self, entries: BTreeMap<String, ScheduleEntry>) -> Result<(), Status> {
+        let previous = std::mem::replace(&mut self.entries, entries);
+        self.save().inspect_err(|_| self.entries = previous)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct ScheduleRequest {
+    category: Option<String>,
+    venue: Option<String>,
+    description: Option<String>,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    #[serde(default)]
+    cancelled: bool,
+    delayed_until: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/schedule", tag = "schedule",
+    responses((status = 200, description = "Schedule metadata keyed by event name", body = BTreeMap<String, ScheduleEntry>))
+)]
+#[get("/api/v3/schedule")]
+fn get_schedule(schedule: &State<SharedSchedule>) -> Json<BTreeMap<String, ScheduleEntry>> {
+    Json(schedule.lock().unwrap().entries.clone())
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/schedule/{event_name}", tag = "schedule",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")), request_body = ScheduleRequest,
+    responses((status = 200, body = ScheduleEntry), (status = 403), (status = 404), (status = 422, description = "Event ends before it starts")),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/schedule/<event_name>", data = "<request>")]
+fn set_schedule(
+    event_name: &str,
+    request: Json<ScheduleRequest>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    events: &State<SharedEvents>,
+    schedule: &State<SharedSchedule>
+) -> Result<Json<ScheduleEntry>, Status> {
+    if !api_keys.can_edit(&api_key, event_name) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    if !events.lock().unwrap().iter().any(|e| e.name == event_name) {
+        return Err(Status::NotFound);
+    }
+
+    let request = request.into_inner();
+    if request.ends_at <= request.starts_at {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let mut schedule = schedule.lock().unwrap();
+    let revision = schedule.entries.get(event_name).map_or(0, |entry| entry.revision + 1);
+    let entry = ScheduleEntry {
+        category: request.category.filter(|c| !c.trim().is_empty()),
+        venue: request.venue,
+        description: request.description,
+        starts_at: request.starts_at,
+        ends_at: request.ends_at,
+        cancelled: request.cancelled,
+        delayed_until: request.delayed_until,
+        revision,
+        updated_at: Utc::now(),
+    };
+
+    schedule.entries.insert(event_name.to_string(), entry.clone());
+    schedule.save()?;
+
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/schedule/{event_name}", tag = "schedule",
+    params(("event_name" = String, Path, description = "Event name as listed by get_events")),
+    responses((status = 200, body = ScheduleEntry), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/schedule/<event_name>")]
+fn delete_schedule(
+    event_name: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    schedule: &State<SharedSchedule>
+) -> Result<Json<ScheduleEntry>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut schedule = schedule.lock().unwrap();
+    let removed = schedule.entries.remove(event_name).ok_or(Status::NotFound)?;
+    schedule.save()?;
+
+    Ok(Json(removed))
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![get_schedule, set_schedule, delete_schedule]
+}
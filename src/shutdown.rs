@@ -0,0 +1,198 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Header, Method};
+use rocket::tokio::time::{sleep, Instant};
+use rocket::{Data, Orbit, Request, Response, Rocket, Route};
+use tracing::{info, warn};
+
+use crate::history::SharedHistory;
+use crate::accounts::Accounts;
+use crate::announcements::SharedAnnouncements;
+use crate::cors::SharedCors;
+use crate::display::SharedDisplays;
+use crate::emergency::{self, SharedEmergency};
+use crate::push::PushNotifier;
+use crate::registration::SharedRegistrations;
+use crate::schedule::SharedSchedule;
+use crate::tokens::Tokens;
+use crate::totp::SecondFactors;
+use crate::webhooks::Webhooks;
+use crate::{persistence, ApiKeys, SharedEvents, STATE_FILE};
+
+const DRAINING_PATH: &str = "/api/v3/draining";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Seconds clients are told to wait before retrying a refused write or reconnecting a stream
+pub const RETRY_AFTER_SECONDS: u64 = 5;
+
+// Set once per write request that was let through, so only those are counted down again
+struct Admitted(bool);
+
+#[derive(Default)]
+pub struct GracefulShutdown {
+    draining: AtomicBool,
+    in_flight: AtomicUsize, // write requests admitted and not yet answered
+}
+
+impl GracefulShutdown {
+    fn is_write(method: Method) -> bool {
+        !matches!(method, Method::Get | Method::Head | Method::Options)
+    }
+
+    // Waits for admitted writes to finish, giving up once the grace period is over
+    async fn drain(&self, grace: Duration) -> bool {
+        let deadline = Instant::now() + grace;
+        while self.in_flight.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        true
+    }
+
+    // Writes every persisted store again, covering any earlier save that failed. The audit log is
+    // appended to line by line and has nothing left to write
+    fn flush(rocket: &Rocket<Orbit>) {
+        if let Some(emergency) = rocket.state::<SharedEmergency>()
+            && let Err(error) = emergency::flush(&emergency.lock().unwrap())
+        {
+            warn!(%error, "failed to flush the emergency override");
+        }
+        if let Some(events) = rocket.state::<SharedEvents>()
+            && let Err(error) = persistence::save(STATE_FILE, &*events.lock().unwrap())
+        {
+            warn!(%error, "failed to flush event state");
+        }
+        if let Some(history) = rocket.state::<SharedHistory>()
+            && let Err(error) = history.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush status history");
+        }
+        if let Some(schedule) = rocket.state::<SharedSchedule>()
+            && let Err(error) = schedule.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush the schedule");
+        }
+        if let Some(registrations) = rocket.state::<SharedRegistrations>()
+            && let Err(error) = registrations.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush registrations");
+        }
+        if let Some(announcements) = rocket.state::<SharedAnnouncements>()
+            && let Err(error) = announcements.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush announcements");
+        }
+        if let Some(displays) = rocket.state::<SharedDisplays>()
+            && let Err(error) = displays.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush displays");
+        }
+        if let Some(cors) = rocket.state::<SharedCors>()
+            && let Err(error) = cors.lock().unwrap().flush()
+        {
+            warn!(%error, "failed to flush CORS origins");
+        }
+        if let Some(webhooks) = rocket.state::<Webhooks>()
+            && let Err(error) = webhooks.flush()
+        {
+            warn!(%error, "failed to flush webhook subscribers");
+        }
+        if let Some(push_notifier) = rocket.state::<PushNotifier>()
+            && let Err(error) = push_notifier.flush()
+        {
+            warn!(%error, "failed to flush push subscriptions");
+        }
+        if let Some(api_keys) = rocket.state::<ApiKeys>()
+            && let Err(error) = api_keys.flush()
+        {
+            warn!(%error, "failed to flush API keys");
+        }
+        if let Some(accounts) = rocket.state::<Accounts>()
+            && let Err(error) = accounts.flush()
+        {
+            warn!(%error, "failed to flush accounts");
+        }
+        if let Some(tokens) = rocket.state::<Tokens>()
+            && let Err(error) = tokens.flush()
+        {
+            warn!(%error, "failed to flush refresh tokens");
+        }
+        if let Some(second_factors) = rocket.state::<SecondFactors>()
+            && let Err(error) = second_factors.flush()
+        {
+            warn!(%error, "failed to flush second factors");
+        }
+    }
+}
+
+// Stops taking writes on shutdown, lets the admitted ones finish and flushes state before Rocket exits.
+// Attach it last so its response hook runs after the audit trail has recorded the request
+#[rocket::async_trait]
+impl Fairing for GracefulShutdown {
+    fn info(&self) -> Info {
+        Info { name: "Graceful shutdown", kind: Kind::Request | Kind::Response | Kind::Shutdown }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        if !Self::is_write(req.method()) {
+            return;
+        }
+
+        // Count first and check second, so a drain that saw zero cannot miss this request
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if !self.draining.load(Ordering::SeqCst) {
+            req.local_cache(|| Admitted(true));
+            return;
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        // Refused writes are routed to `draining` instead of their handler
+        req.set_method(Method::Get);
+        req.set_uri(Origin::parse(DRAINING_PATH).unwrap());
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, _: &mut Response<'r>) {
+        if req.local_cache(|| Admitted(false)).0 {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let grace = Duration::from_secs(rocket.config().shutdown.grace as u64);
+        let pending = self.in_flight.load(Ordering::SeqCst);
+        info!(pending, grace_seconds = grace.as_secs(), "shutting down, no longer accepting writes");
+
+        if !self.drain(grace).await {
+            warn!(pending = self.in_flight.load(Ordering::SeqCst), "grace period over with writes still in flight");
+        }
+
+        Self::flush(rocket);
+        info!("state flushed to disk");
+    }
+}
+
+#[derive(Responder)]
+#[response(status = 503)]
+struct Draining {
+    message: &'static str,
+    retry_after: Header<'static>,
+}
+
+#[get("/api/v3/draining")]
+fn draining() -> Draining {
+    Draining {
+        message: "Server is restarting, retry shortly",
+        retry_after: Header::new("Retry-After", RETRY_AFTER_SECONDS.to_string()),
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![draining]
+}
@@ -0,0 +1,126 @@
+use askama::Template;
+use chrono::{DateTime, Utc};
+use rocket::http::Status;
+use rocket::response::content::RawHtml;
+use rocket::{Route, State};
+
+use crate::announcements::{Priority, SharedAnnouncements};
+use crate::emergency::SharedEmergency;
+use crate::history::SharedHistory;
+use crate::{EventStatus, SharedEvents};
+
+// Fallback for browsers without JavaScript or the live stream
+const REFRESH_SECONDS: u32 = 60;
+
+impl Priority {
+    pub fn tone(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        }
+    }
+}
+
+pub struct Timestamp {
+    pub iso: String,
+    pub display: String,
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(at: DateTime<Utc>) -> Self {
+        Timestamp { iso: at.to_rfc3339(), display: at.format("%d %b, %H:%M UTC").to_string() }
+    }
+}
+
+struct EventRow {
+    name: String,
+    label: &'static str,
+    tone: &'static str,
+    updated: Option<Timestamp>,
+}
+
+struct NoticeRow {
+    id: String,
+    message: String,
+    tone: &'static str,
+    events: String,
+    posted: Timestamp,
+}
+
+#[derive(Template)]
+#[template(path = "status.html")]
+struct StatusPage {
+    refresh_seconds: u32,
+    statuses: String, // label and tone of every status as JSON, for patching rows from the live stream
+    emergency: Option<String>,
+    events: Vec<EventRow>,
+    announcements: Vec<NoticeRow>,
+    generated: Timestamp,
+}
+
+// Not rate limited: behind campus NAT many visitors share one address, and the page patches itself
+// from the live stream instead of reloading
+#[get("/")]
+fn status_page(
+    events: &State<SharedEvents>,
+    history: &State<SharedHistory>,
+    announcements: &State<SharedAnnouncements>,
+    emergency: &State<SharedEmergency>
+) -> Result<RawHtml<String>, Status> {
+    let emergency = emergency.lock().unwrap().as_ref().map(|e| e.message.clone());
+    let events = events.lock().unwrap();
+    let history = history.lock().unwrap();
+
+    let events = events
+        .iter()
+        .map(|event| EventRow {
+            name: event.name.clone(),
+            label: event.status.label(),
+            tone: event.status.tone(),
+            updated: history
+                .entries()
+                .iter()
+                .rev()
+                .find(|entry| entry.event == event.name)
+                .map(|entry| entry.at.into()),
+        })
+        .collect();
+
+    let announcements = announcements
+        .lock()
+        .unwrap()
+        .active()
+        .map(|announcement| NoticeRow {
+            id: announcement.id.clone(),
+            message: announcement.message.clone(),
+            tone: announcement.priority.tone(),
+            events: announcement.events.join(", "),
+            posted: announcement.created_at.into(),
+        })
+        .collect();
+
+    let statuses: serde_json::Map<String, serde_json::Value> = EventStatus::ALL
+        .iter()
+        .filter_map(|status| {
+            let name = serde_json::to_value(status).ok()?.as_str()?.to_string();
+            Some((name, serde_json::json!({ "label": status.label(), "tone": status.tone() })))
+        })
+        .collect();
+
+    let page = StatusPage {
+        refresh_seconds: REFRESH_SECONDS,
+        statuses: serde_json::Value::Object(statuses).to_string(),
+        emergency,
+        events,
+        announcements,
+        generated: Utc::now().into(),
+    };
+
+    page.render().map(RawHtml).map_err(|_| Status::InternalServerError)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![status_page]
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use rocket::response::stream::{Event, EventStream};
+use rocket::serde::Serialize;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{Route, Shutdown, State};
+use utoipa::ToSchema;
+
+use crate::announcements::{Announcement, SharedAnnouncements};
+use crate::emergency::SharedEmergency;
+use crate::metrics::METRICS;
+use crate::registration::{ParticipationCounts, RegistrationChange};
+use crate::shutdown;
+use crate::{EventDetail, EventStatus, SharedEvents};
+
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+pub enum LiveUpdate {
+    Events { events: Vec<EventDetail> },
+    StatusChanged { event: String, from: EventStatus, to: EventStatus, by: String },
+    EventAdded { event: String, status: EventStatus, by: String },
+    EventRemoved { event: String, status: EventStatus, by: String }, // status it had when removed
+    Announcement { announcement: Announcement },
+    AnnouncementRemoved { id: String },
+    RegistrationsChanged { event: String, change: RegistrationChange, counts: ParticipationCounts },
+    Emergency { message: Option<String> },
+    Restarting { retry_after_seconds: u64 },
+}
+
+impl LiveUpdate {
+    pub fn status_changes(before: &[EventDetail], after: &[EventDetail], by: &str) -> Vec<LiveUpdate> {
+        after
+            .iter()
+            .filter_map(|event| {
+                let previous = before.iter().find(|e| e.name == event.name)?;
+                (previous.status != event.status).then(|| LiveUpdate::StatusChanged {
+                    event: event.name.clone(),
+                    from: previous.status.clone(),
+                    to: event.status.clone(),
+                    by: by.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    // Events that appeared or disappeared, as an import does to the lineup
+    pub fn lineup_changes(before: &[EventDetail], after: &[EventDetail], by: &str) -> Vec<LiveUpdate> {
+        let added = after.iter().filter(|event| !before.iter().any(|e| e.name == event.name)).map(|event| {
+            LiveUpdate::EventAdded { event: event.name.clone(), status: event.status.clone(), by: by.to_string() }
+        });
+        let removed = before.iter().filter(|event| !after.iter().any(|e| e.name == event.name)).map(|event| {
+            LiveUpdate::EventRemoved { event: event.name.clone(), status: event.status.clone(), by: by.to_string() }
+        });
+        added.chain(removed).collect()
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LiveUpdate::Events { .. } => "events",
+            LiveUpdate::StatusChanged { .. } => "status_changed",
+            LiveUpdate::EventAdded { .. } => "event_added",
+            LiveUpdate::EventRemoved { .. } => "event_removed",
+            LiveUpdate::Announcement { .. } => "announcement",
+            LiveUpdate::AnnouncementRemoved { .. } => "announcement_removed",
+            LiveUpdate::RegistrationsChanged { .. } => "registrations_changed",
+            LiveUpdate::Emergency { .. } => "emergency",
+            LiveUpdate::Restarting { .. } => "restarting",
+        }
+    }
+
+    fn to_event(&self) -> Event {
+        Event::json(self).event(self.kind())
+    }
+}
+
+pub struct LiveUpdates {
+    sender: broadcast::Sender<LiveUpdate>,
+}
+
+impl LiveUpdates {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        LiveUpdates { sender }
+    }
+
+    // Nobody listening is not an error, the update is simply dropped
+    pub fn publish(&self, update: LiveUpdate) {
+        let _ = self.sender.send(update);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.sender.subscribe()
+    }
+}
+
+// Tracks a connected stream client for as long as its response is alive
+struct SubscriberGauge;
+
+impl SubscriberGauge {
+    fn connect() -> Self {
+        METRICS.stream_subscribers.inc();
+        SubscriberGauge
+    }
+}
+
+impl Drop for SubscriberGauge {
+    fn drop(&mut self) {
+        METRICS.stream_subscribers.dec();
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/stream", tag = "events",
+    responses((status = 200, description = "Server-sent events, one LiveUpdate per message", content_type = "text/event-stream", body = LiveUpdate))
+)]
+#[get("/api/v3/stream")]
+fn stream(
+    updates: &State<LiveUpdates>,
+    events: &State<SharedEvents>,
+    announcements: &State<SharedAnnouncements>,
+    emergency: &State<SharedEmergency>,
+    mut shutdown: Shutdown
+) -> EventStream![] {
+    let mut rx = updates.subscribe();
+
+    // Every subscriber starts from a full snapshot before receiving changes
+    let mut snapshot = vec![LiveUpdate::Events { events: events.lock().unwrap().clone() }];
+    if let Some(emergency) = emergency.lock().unwrap().as_ref() {
+        snapshot.push(LiveUpdate::Emergency { message: Some(emergency.message.clone()) });
+    }
+    snapshot.extend(
+        announcements
+            .lock()
+            .unwrap()
+            .active()
+            .map(|announcement| LiveUpdate::Announcement { announcement: announcement.clone() }),
+    );
+
+    let gauge = SubscriberGauge::connect();
+
+    EventStream! {
+        let _gauge = gauge;
+        for update in snapshot {
+            yield update.to_event();
+        }
+
+        loop {
+            let update = select! {
+                msg = rx.recv() => match msg {
+                    Ok(update) => update,
+                    Err(RecvError::Lagged(missed)) => {
+                        METRICS.updates_missed("stream", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => {
+                    // Last frame before the connection closes, browsers reconnect after the retry delay
+                    let retry_after_seconds = shutdown::RETRY_AFTER_SECONDS;
+                    yield LiveUpdate::Restarting { retry_after_seconds }
+                        .to_event()
+                        .with_retry(Duration::from_secs(retry_after_seconds));
+                    break;
+                },
+            };
+
+            yield update.to_event();
+        }
+    }
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![stream]
+}
@@ -0,0 +1,499 @@
+use std::sync::{Mutex, OnceLock};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use dotenvy::var;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Route, State};
+use rocket_governor::RocketGovernor;
+use sha2::Sha256;
+use utoipa::ToSchema;
+
+use crate::keys::{self, Principal, Scope};
+use crate::{persistence, totp, ApiKey, ApiKeys, RateLimitGuard};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const TOKENS_FILE: &str = "tokens.json";
+const ISSUER: &str = "adharva";
+const REFRESH_PREFIX: &str = "adr_";
+// base64url of {"alg":"HS256","typ":"JWT"}, every access token starts with it
+const JWT_HEADER: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9";
+
+fn default_access_minutes() -> i64 {
+    15
+}
+
+fn default_refresh_days() -> i64 {
+    30
+}
+
+// The `tokens` table of the active Rocket.toml profile
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TokenConfig {
+    #[serde(default = "default_access_minutes")]
+    access_minutes: i64,
+    #[serde(default = "default_refresh_days")]
+    refresh_days: i64,
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        TokenConfig { access_minutes: default_access_minutes(), refresh_days: default_refresh_days() }
+    }
+}
+
+// What an access token carries, readable by anyone holding it but only signed by this server
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Claims {
+    iss: String,
+    sub: String, // id of the key the token was exchanged from
+    name: String,
+    scopes: Vec<Scope>,
+    events: Vec<String>, // events the token may edit, empty for admin tokens which may edit all
+    iat: i64,
+    exp: i64,
+    fam: String, // refresh family, so logging a device out also ends its access token
+}
+
+// Refresh tokens are single use. Each refresh replaces the token with a new one in the same family,
+// and presenting a replaced token again ends the whole family since it must have been copied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct RefreshToken {
+    family: String,
+    key_id: String,
+    token_hash: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    stepped_up_until: Option<DateTime<Utc>>, // admin actions are allowed until then, see /api/v3/2fa/verify
+}
+
+// What presenting a refresh token did to the store
+enum Redemption {
+    Fresh(RefreshToken), // now marked used, a replacement can be issued in its family
+    Reused, // it had been used before, so its whole family is revoked
+    Invalid, // unknown, expired or revoked
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct RefreshStore {
+    tokens: Vec<RefreshToken>,
+}
+
+impl RefreshStore {
+    fn save(&mut self) -> Result<(), Status> {
+        let now = Utc::now();
+        self.tokens.retain(|token| token.expires_at > now);
+        persistence::save(TOKENS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    fn revoke_family(&mut self, family: &str, now: DateTime<Utc>) {
+        for token in self.tokens.iter_mut().filter(|token| token.family == family) {
+            token.revoked_at.get_or_insert(now);
+        }
+    }
+
+    fn redeem(&mut self, token_hash: &str, now: DateTime<Utc>) -> Redemption {
+        let Some(token) = self.tokens.iter_mut().find(|token| token.token_hash == token_hash) else {
+            return Redemption::Invalid;
+        };
+        if token.revoked_at.is_some() || token.expires_at <= now {
+            return Redemption::Invalid;
+        }
+        if token.used_at.is_some() {
+            let family = token.family.clone();
+            self.revoke_family(&family, now);
+            return Redemption::Reused;
+        }
+
+        token.used_at = Some(now);
+        Redemption::Fresh(token.clone())
+    }
+
+    fn family_active(&self, family: &str) -> bool {
+        let mut tokens = self.tokens.iter().filter(|token| token.family == family).peekable();
+        tokens.peek().is_some() && tokens.all(|token| token.revoked_at.is_none())
+    }
+
+    fn stepped_up_until(&self, family: &str) -> Option<DateTime<Utc>> {
+        self.tokens.iter().filter(|token| token.family == family).filter_map(|token| token.stepped_up_until).max()
+    }
+}
+
+pub fn is_access_token(bearer: &str) -> bool {
+    bearer.starts_with(JWT_HEADER) && bearer.matches('.').count() == 2
+}
+
+pub struct Tokens {
+    secret: Vec<u8>,
+    ephemeral: bool, // no TOKEN_SECRET, so access tokens stop working when the server restarts
+    config: TokenConfig,
+    store: Mutex<RefreshStore>,
+}
+
+impl Tokens {
+    pub fn load() -> Self {
+        // Generated once per process, since a certificate reload relaunches Rocket and must not log everyone out
+        static GENERATED_SECRET: OnceLock<[u8; 32]> = OnceLock::new();
+        let (secret, ephemeral) = match var("TOKEN_SECRET") {
+            Ok(secret) => (secret.into_bytes(), false),
+            Err(_) => (GENERATED_SECRET.get_or_init(rand::random).to_vec(), true),
+        };
+        Tokens {
+            secret,
+            ephemeral,
+            config: rocket::Config::figment().extract_inner("tokens").unwrap_or_default(),
+            store: Mutex::new(persistence::load(TOKENS_FILE).unwrap_or_default()),
+        }
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+
+    fn sign(&self, claims: &Claims) -> String {
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_string(claims).unwrap_or_default());
+        let signing_input = format!("{}.{}", JWT_HEADER, claims);
+        let mut mac = self.mac();
+        mac.update(signing_input.as_bytes());
+        format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()))
+    }
+
+    // Only HS256 tokens with this server's header are accepted, so `alg` cannot be swapped
+    fn verify(&self, token: &str) -> Option<Claims> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signing_input.split_once('.')?;
+        if header != JWT_HEADER {
+            return None;
+        }
+
+        let mut mac = self.mac();
+        mac.update(signing_input.as_bytes());
+        mac.verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+
+        let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).ok()?).ok()?;
+        (claims.iss == ISSUER).then_some(claims)
+    }
+
+    // Resolves an access token for the ApiKey guard. The error names the metrics reason
+    pub fn authenticate(&self, token: &str, api_keys: &ApiKeys) -> Result<ApiKey, &'static str> {
+        let claims = self.verify(token).ok_or("invalid_token")?;
+        if claims.exp <= Utc::now().timestamp() {
+            return Err("expired_token");
+        }
+        let stepped_up = {
+            let store = self.store.lock().unwrap();
+            if !store.family_active(&claims.fam) {
+                return Err("revoked_token");
+            }
+            store.stepped_up_until(&claims.fam).is_some_and(|until| until > Utc::now())
+        };
+
+        // Revoking the key ends its tokens at once rather than when they expire
+        let (name, scopes, _) = api_keys.active(&claims.sub).ok_or("revoked_key")?;
+        Ok(ApiKey::new(Principal::Token { key: claims.sub, family: claims.fam }, name, scopes).with_second_factor(stepped_up))
+    }
+
+    // Lets a token family act as admin for a while after its second factor was checked
+    pub fn step_up(&self, family: &str, until: DateTime<Utc>) -> Result<DateTime<Utc>, Status> {
+        let mut store = self.store.lock().unwrap();
+        if !store.family_active(family) {
+            return Err(Status::Unauthorized);
+        }
+        for token in store.tokens.iter_mut().filter(|token| token.family == family) {
+            token.stepped_up_until = Some(until);
+        }
+        store.save()?;
+        Ok(until)
+    }
+
+    // Neither token outlives the key it was exchanged from
+    fn issue(
+        &self,
+        store: &mut RefreshStore,
+        key_id: &str,
+        family: String,
+        (name, scopes, key_expires_at): (String, Vec<Scope>, Option<DateTime<Utc>>),
+        stepped_up_until: Option<DateTime<Utc>>
+    ) -> Result<TokenPair, Status> {
+        let now = Utc::now();
+        let cap = |at: DateTime<Utc>| key_expires_at.map_or(at, |key| at.min(key));
+        let access_expires_at = cap(now + Duration::minutes(self.config.access_minutes));
+        let refresh_expires_at = cap(now + Duration::days(self.config.refresh_days));
+
+        let claims = Claims {
+            iss: ISSUER.to_string(),
+            sub: key_id.to_string(),
+            name,
+            events: scopes
+                .iter()
+                .filter_map(|scope| match scope {
+                    Scope::Event(event) => Some(event.clone()),
+                    Scope::Admin => None,
+                })
+                .collect(),
+            scopes,
+            iat: now.timestamp(),
+            exp: access_expires_at.timestamp(),
+            fam: family.clone(),
+        };
+
+        let random: String = rand::thread_rng().sample_iter(&Alphanumeric).take(48).map(char::from).collect();
+        let refresh_token = format!("{}{}", REFRESH_PREFIX, random);
+        store.tokens.push(RefreshToken {
+            family,
+            key_id: key_id.to_string(),
+            token_hash: keys::hash(&refresh_token),
+            created_at: now,
+            expires_at: refresh_expires_at,
+            used_at: None,
+            revoked_at: None,
+            stepped_up_until,
+        });
+        store.save()?;
+
+        Ok(TokenPair {
+            access_token: self.sign(&claims),
+            token_type: "Bearer",
+            expires_in: (access_expires_at - now).num_seconds(),
+            expires_at: access_expires_at,
+            refresh_token,
+            refresh_expires_at,
+        })
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(TOKENS_FILE, &*self.store.lock().unwrap())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TokenPair {
+    access_token: String, // a JWT, sent as `Authorization: Bearer` like an API key
+    token_type: &'static str,
+    expires_in: i64, // seconds
+    expires_at: DateTime<Utc>,
+    refresh_token: String, // single use, exchange it at /api/v3/token/refresh before the access token expires
+    refresh_expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+// Meant to be called once per device, which then only ever holds short-lived credentials
+#[utoipa::path(
+    post, path = "/api/v3/token", tag = "keys",
+    responses((status = 200, description = "An access token carrying the key's identity and events, and a refresh token. With a valid X-OTP both count as stepped up for 15 minutes", body = TokenPair), (status = 400, description = "Presented an access token or a session instead of an API key"), (status = 401), (status = 429)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/token")]
+fn exchange_key(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    tokens: &State<Tokens>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<TokenPair>, Status> {
+    // An access token could otherwise be swapped for fresh ones forever
+    let Principal::Key(key_id) = &api_key.principal else {
+        return Err(Status::BadRequest);
+    };
+    let key = api_keys.active(key_id).ok_or(Status::Unauthorized)?;
+
+    // A valid X-OTP on the exchange covers the new tokens like a step-up, so a script can make several
+    // admin calls with one code
+    let stepped_up_until = api_key.has_second_factor().then(|| Utc::now() + Duration::minutes(totp::STEP_UP_MINUTES));
+    let family: String = rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect();
+    let mut store = tokens.store.lock().unwrap();
+    Ok(Json(tokens.issue(&mut store, key_id, family, key, stepped_up_until)?))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/token/refresh", tag = "keys", request_body = RefreshRequest,
+    responses((status = 200, description = "A new access token and a new refresh token, the old refresh token stops working", body = TokenPair), (status = 401, description = "Unknown, expired, revoked or already used refresh token, or its key was revoked"), (status = 429))
+)]
+#[post("/api/v3/token/refresh", data = "<request>")]
+fn refresh(
+    request: Json<RefreshRequest>,
+    api_keys: &State<ApiKeys>,
+    tokens: &State<Tokens>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<TokenPair>, Status> {
+    let now = Utc::now();
+    let token_hash = keys::hash(request.refresh_token.trim());
+
+    let mut store = tokens.store.lock().unwrap();
+    let token = match store.redeem(&token_hash, now) {
+        Redemption::Fresh(token) => token,
+        Redemption::Reused => {
+            store.save()?;
+            crate::metrics::METRICS.auth_failure("reused_refresh_token");
+            return Err(Status::Unauthorized);
+        }
+        Redemption::Invalid => return Err(Status::Unauthorized),
+    };
+    let Some(key) = api_keys.active(&token.key_id) else {
+        store.revoke_family(&token.family, now);
+        store.save()?;
+        return Err(Status::Unauthorized);
+    };
+
+    Ok(Json(tokens.issue(&mut store, &token.key_id, token.family, key, token.stepped_up_until)?))
+}
+
+// Logs a device out: its refresh token and any access token issued alongside stop working
+#[utoipa::path(
+    post, path = "/api/v3/token/revoke", tag = "keys", request_body = RefreshRequest,
+    responses((status = 204), (status = 404, description = "Unknown refresh token"))
+)]
+#[post("/api/v3/token/revoke", data = "<request>")]
+fn revoke(request: Json<RefreshRequest>, tokens: &State<Tokens>) -> Result<Status, Status> {
+    let token_hash = keys::hash(request.refresh_token.trim());
+
+    let mut store = tokens.store.lock().unwrap();
+    let family = store
+        .tokens
+        .iter()
+        .find(|token| token.token_hash == token_hash)
+        .map(|token| token.family.clone())
+        .ok_or(Status::NotFound)?;
+    store.revoke_family(&family, Utc::now());
+    store.save()?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![exchange_key, refresh, revoke]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens() -> Tokens {
+        Tokens {
+            secret: b"test secret".to_vec(),
+            ephemeral: true,
+            config: TokenConfig::default(),
+            store: Mutex::new(RefreshStore::default()),
+        }
+    }
+
+    fn claims(scopes: Vec<Scope>) -> Claims {
+        let now = Utc::now().timestamp();
+        Claims {
+            iss: ISSUER.to_string(),
+            sub: "key1".to_string(),
+            name: "Yukti desk".to_string(),
+            scopes,
+            events: vec!["Yukti".to_string()],
+            iat: now,
+            exp: now + 900,
+            fam: "family1".to_string(),
+        }
+    }
+
+    fn refresh_token(family: &str, secret: &str, now: DateTime<Utc>) -> RefreshToken {
+        RefreshToken {
+            family: family.to_string(),
+            key_id: "key1".to_string(),
+            token_hash: keys::hash(secret),
+            created_at: now,
+            expires_at: now + Duration::days(1),
+            used_at: None,
+            revoked_at: None,
+            stepped_up_until: None,
+        }
+    }
+
+    #[test]
+    fn signed_token_verifies() {
+        let tokens = tokens();
+        let token = tokens.sign(&claims(vec![Scope::Event("Yukti".to_string())]));
+
+        assert!(is_access_token(&token));
+        assert_eq!(tokens.verify(&token).map(|claims| claims.sub), Some("key1".to_string()));
+    }
+
+    #[test]
+    fn tampered_claims_are_rejected() {
+        let tokens = tokens();
+        let token = tokens.sign(&claims(vec![Scope::Event("Yukti".to_string())]));
+        let (_, signature) = token.rsplit_once('.').unwrap();
+
+        let escalated = URL_SAFE_NO_PAD.encode(serde_json::to_string(&claims(vec![Scope::Admin])).unwrap());
+        let forged = format!("{}.{}.{}", JWT_HEADER, escalated, signature);
+        assert!(tokens.verify(&forged).is_none());
+    }
+
+    #[test]
+    fn swapped_algorithm_is_rejected() {
+        let tokens = tokens();
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_string(&claims(vec![Scope::Admin])).unwrap());
+
+        let none = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+        assert!(tokens.verify(&format!("{}.{}.", none, payload)).is_none());
+
+        // Correctly MACed with our secret, but under a header this server never issues
+        let hs512 = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS512","typ":"JWT"}"#);
+        let signing_input = format!("{}.{}", hs512, payload);
+        let mut mac = tokens.mac();
+        mac.update(signing_input.as_bytes());
+        let token = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()));
+        assert!(tokens.verify(&token).is_none());
+    }
+
+    #[test]
+    fn token_from_another_secret_is_rejected() {
+        let other = Tokens { secret: b"another secret".to_vec(), ..tokens() };
+        let token = other.sign(&claims(vec![Scope::Admin]));
+
+        assert!(tokens().verify(&token).is_none());
+    }
+
+    #[test]
+    fn reused_refresh_token_revokes_its_family() {
+        let now = Utc::now();
+        let mut store = RefreshStore::default();
+        store.tokens.push(refresh_token("family1", "adr_first", now));
+        store.tokens.push(refresh_token("family2", "adr_other", now));
+
+        assert!(matches!(store.redeem(&keys::hash("adr_first"), now), Redemption::Fresh(_)));
+        // The replacement a refresh would have issued
+        store.tokens.push(refresh_token("family1", "adr_second", now));
+        assert!(store.family_active("family1"));
+
+        assert!(matches!(store.redeem(&keys::hash("adr_first"), now), Redemption::Reused));
+        assert!(!store.family_active("family1"));
+        assert!(matches!(store.redeem(&keys::hash("adr_second"), now), Redemption::Invalid));
+        assert!(store.family_active("family2"));
+    }
+
+    #[test]
+    fn expired_or_unknown_refresh_tokens_are_invalid() {
+        let now = Utc::now();
+        let mut store = RefreshStore::default();
+        store.tokens.push(refresh_token("family1", "adr_first", now - Duration::days(2)));
+
+        assert!(matches!(store.redeem(&keys::hash("adr_first"), now), Redemption::Invalid));
+        assert!(matches!(store.redeem(&keys::hash("adr_unknown"), now), Redemption::Invalid));
+    }
+}
@@ -0,0 +1,616 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use qrcode::{render::svg, QrCode};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Request, Response, Route, State};
+use rocket_governor::RocketGovernor;
+use totp_rs::{Algorithm, Secret, TOTP};
+use utoipa::ToSchema;
+
+use crate::accounts::Accounts;
+use crate::tokens::Tokens;
+use crate::keys::{self, Principal};
+use crate::{metrics, persistence, ApiKey, ApiKeys, RateLimitGuard};
+
+const TOTP_FILE: &str = "totp.json";
+pub const OTP_HEADER: &str = "X-OTP";
+pub const OTP_REQUIRED_HEADER: &str = "X-OTP-Required";
+const ISSUER: &str = "Adharva";
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+const SKEW_STEPS: u64 = 1; // codes from the previous and next 30 seconds are accepted for clock drift
+const BACKUP_CODES: usize = 10;
+const FREE_ATTEMPTS: u32 = 5; // wrong codes in a row before the factor locks
+const LOCKOUT_SECONDS: i64 = 60; // first lockout, doubling with each further wrong code
+const MAX_LOCKOUT_SECONDS: i64 = 3600;
+pub const STEP_UP_MINUTES: i64 = 15; // how long a verified code lifts a browser session or token family to admin
+
+// Whose second factor it is. Access tokens share the factor of the key they came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", tag = "kind", content = "id", rename_all = "lowercase")]
+enum Owner {
+    Key(String),
+    Account(String),
+}
+
+impl From<&Principal> for Owner {
+    fn from(principal: &Principal) -> Self {
+        match principal {
+            Principal::Key(id) | Principal::Token { key: id, .. } => Owner::Key(id.clone()),
+            Principal::Account { user, .. } => Owner::Account(user.clone()),
+        }
+    }
+}
+
+// Secrets are kept as base32, backup codes only as hashes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Factor {
+    owner: Owner,
+    label: String, // account name shown in the authenticator app
+    secret: Option<String>, // set once enrolment is confirmed with a first code
+    pending_secret: Option<String>, // enrolment started but not confirmed yet
+    confirmed_at: Option<DateTime<Utc>>,
+    last_step: u64, // codes from this or earlier time steps are refused, so a seen code cannot be replayed
+    backup_codes: Vec<String>,
+    // Wrong codes in a row, from any IP, and until when every code is refused because of them
+    #[serde(default)]
+    failed_attempts: u32,
+    #[serde(default)]
+    locked_until: Option<DateTime<Utc>>,
+}
+
+enum Verification {
+    Accepted,
+    Rejected,
+    Locked(DateTime<Utc>),
+}
+
+fn totp(secret: &str, label: &str) -> Option<TOTP> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+    TOTP::new(Algorithm::SHA1, DIGITS, 0, STEP_SECONDS, secret, Some(ISSUER.to_string()), label.replace(':', " ")).ok()
+}
+
+// The time step a code belongs to, if it matches one close to now
+fn matching_step(totp: &TOTP, code: &str, at: DateTime<Utc>) -> Option<u64> {
+    let now = at.timestamp() as u64 / STEP_SECONDS;
+    (now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS).find(|step| totp.check(code, step * STEP_SECONDS))
+}
+
+fn backup_codes() -> Vec<String> {
+    (0..BACKUP_CODES)
+        .map(|_| {
+            let code: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .filter(u8::is_ascii_lowercase)
+                .take(8)
+                .map(char::from)
+                .collect();
+            format!("{}-{}", &code[..4], &code[4..])
+        })
+        .collect()
+}
+
+fn normalize_backup_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase().replace([' ', '-'], "")
+}
+
+impl Factor {
+    fn is_enrolled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    // A lock holds even for the right code, otherwise it would not slow down guessing
+    fn verify(&mut self, code: &str, now: DateTime<Utc>) -> Verification {
+        if let Some(until) = self.locked_until.filter(|until| *until > now) {
+            return Verification::Locked(until);
+        }
+        if self.accepts(code, now) {
+            self.failed_attempts = 0;
+            self.locked_until = None;
+            return Verification::Accepted;
+        }
+
+        self.failed_attempts += 1;
+        if self.failed_attempts >= FREE_ATTEMPTS {
+            let doublings = (self.failed_attempts - FREE_ATTEMPTS).min(6);
+            let seconds = (LOCKOUT_SECONDS << doublings).min(MAX_LOCKOUT_SECONDS);
+            self.locked_until = Some(now + Duration::seconds(seconds));
+        }
+        Verification::Rejected
+    }
+
+    fn accepts(&mut self, code: &str, now: DateTime<Utc>) -> bool {
+        let code = code.trim();
+        // Each code works once (RFC 6238 §5.2). Several admin calls in a row step up a session or token instead
+        if let Some(totp) = self.secret.as_deref().and_then(|secret| totp(secret, &self.label))
+            && let Some(step) = matching_step(&totp, code, now)
+            && step > self.last_step
+        {
+            self.last_step = step;
+            return true;
+        }
+
+        let hash = keys::hash(&normalize_backup_code(code));
+        let before = self.backup_codes.len();
+        self.backup_codes.retain(|backup| *backup != hash);
+        self.backup_codes.len() < before
+    }
+
+    // Returns the codes in the clear, this is the only time they are shown
+    fn new_backup_codes(&mut self) -> Vec<String> {
+        let codes = backup_codes();
+        self.backup_codes = codes.iter().map(|code| keys::hash(&normalize_backup_code(code))).collect();
+        codes
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct FactorStore {
+    factors: Vec<Factor>,
+}
+
+impl FactorStore {
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(TOTP_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+
+    fn get_mut(&mut self, owner: &Owner) -> Option<&mut Factor> {
+        self.factors.iter_mut().find(|factor| factor.owner == *owner)
+    }
+}
+
+// What the ApiKey guard found out about the second factor, for the X-OTP-Required hint
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StepUp {
+    NotNeeded,
+    Enroll, // admin scope but nothing enrolled yet
+    Code,   // admin scope, enrolled, but no valid code presented
+    Locked(DateTime<Utc>), // too many wrong codes, none is accepted until then
+}
+
+#[derive(Default)]
+pub struct SecondFactors {
+    store: Mutex<FactorStore>,
+}
+
+impl SecondFactors {
+    pub fn load() -> Self {
+        SecondFactors { store: Mutex::new(persistence::load(TOTP_FILE).unwrap_or_default()) }
+    }
+
+    pub fn any_enrolled(&self) -> bool {
+        self.store.lock().unwrap().factors.iter().any(Factor::is_enrolled)
+    }
+
+    // Called by the ApiKey guard. Only admin scope ever needs a second factor, presented in the
+    // X-OTP header on each request or once per browser session at /api/v3/2fa/verify
+    pub fn check(&self, key: ApiKey, req: &Request<'_>) -> ApiKey {
+        if !key.has_admin_scope() || key.has_second_factor() {
+            return key;
+        }
+
+        let owner = Owner::from(&key.principal);
+        let mut store = self.store.lock().unwrap();
+        let Some(factor) = store.get_mut(&owner).filter(|factor| factor.is_enrolled()) else {
+            req.local_cache(|| StepUp::Enroll);
+            return key;
+        };
+        let Some(code) = req.headers().get_one(OTP_HEADER) else {
+            req.local_cache(|| StepUp::Code);
+            return key;
+        };
+
+        let verification = factor.verify(code, Utc::now());
+        // Losing a used step, backup code or failure count is not worth failing the request over
+        let _ = store.save();
+        match verification {
+            Verification::Accepted => key.with_second_factor(true),
+            Verification::Rejected => {
+                metrics::METRICS.auth_failure("invalid_otp");
+                req.local_cache(|| StepUp::Code);
+                key
+            }
+            Verification::Locked(until) => {
+                metrics::METRICS.auth_failure("otp_locked");
+                req.local_cache(|| StepUp::Locked(until));
+                key
+            }
+        }
+    }
+
+    // A rotated key keeps the factor of the key it replaces
+    pub fn carry_over(&self, old_key: &str, new_key: &str) -> Result<(), Status> {
+        let mut store = self.store.lock().unwrap();
+        let Some(factor) = store.get_mut(&Owner::Key(old_key.to_string())) else {
+            return Ok(());
+        };
+        let mut factor = factor.clone();
+        factor.owner = Owner::Key(new_key.to_string());
+        store.factors.push(factor);
+        store.save().inspect_err(|_| {
+            store.factors.pop();
+        })
+    }
+
+    pub fn remove_account(&self, user_id: &str) -> Result<(), Status> {
+        let mut store = self.store.lock().unwrap();
+        store.factors.retain(|factor| factor.owner != Owner::Account(user_id.to_string()));
+        store.save()
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(TOTP_FILE, &*self.store.lock().unwrap())
+    }
+}
+
+// Tells clients why an admin request was refused, so they can ask for a code or start enrolment
+pub struct StepUpHint;
+
+#[rocket::async_trait]
+impl Fairing for StepUpHint {
+    fn info(&self) -> Info {
+        Info { name: "Two-factor step-up hint", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if res.status() != Status::Forbidden {
+            return;
+        }
+        let hint = match req.local_cache(|| StepUp::NotNeeded) {
+            StepUp::NotNeeded => return,
+            StepUp::Enroll => "enroll",
+            StepUp::Code => "code",
+            StepUp::Locked(until) => {
+                let seconds = (*until - Utc::now()).num_seconds().max(1);
+                res.set_header(Header::new("Retry-After", seconds.to_string()));
+                "locked"
+            }
+        };
+        res.set_header(Header::new(OTP_REQUIRED_HEADER, hint));
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorStatus {
+    enrolled: bool,
+    pending: bool, // enrolment started, waiting for the first code
+    confirmed_at: Option<DateTime<Utc>>,
+    backup_codes_left: usize,
+    locked_until: Option<DateTime<Utc>>, // set after too many wrong codes, no code works until then
+    verified: bool, // this request carried a valid code, or its session has stepped up
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Enrollment {
+    secret: String, // base32, for typing into an authenticator app by hand
+    provisioning_uri: String, // otpauth:// URI, also available as a QR code
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct BackupCodes {
+    codes: Vec<String>, // each works once in place of a TOTP code, shown only this time
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct SteppedUp {
+    verified_until: Option<DateTime<Utc>>, // for sessions and access tokens, API keys send X-OTP with each admin request instead
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct CodeRequest {
+    code: String, // a 6 digit TOTP code or a backup code
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/2fa", tag = "two-factor",
+    responses((status = 200, body = TwoFactorStatus), (status = 401)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[get("/api/v3/2fa")]
+fn status(api_key: ApiKey, second_factors: &State<SecondFactors>) -> Json<TwoFactorStatus> {
+    let mut store = second_factors.store.lock().unwrap();
+    let factor = store.get_mut(&Owner::from(&api_key.principal));
+    Json(TwoFactorStatus {
+        enrolled: factor.as_ref().is_some_and(|factor| factor.is_enrolled()),
+        pending: factor.as_ref().is_some_and(|factor| factor.pending_secret.is_some()),
+        confirmed_at: factor.as_ref().and_then(|factor| factor.confirmed_at),
+        backup_codes_left: factor.as_ref().map_or(0, |factor| factor.backup_codes.len()),
+        locked_until: factor.as_ref().and_then(|factor| factor.locked_until).filter(|until| *until > Utc::now()),
+        verified: api_key.has_second_factor(),
+    })
+}
+
+// Starts enrolment for the credential making the request. Replacing a confirmed factor needs a
+// code from it, otherwise a leaked key could enrol its own authenticator
+#[utoipa::path(
+    post, path = "/api/v3/2fa/enroll", tag = "two-factor",
+    responses((status = 200, description = "A new secret, active once confirmed with a code", body = Enrollment), (status = 403, description = "Not admin scope, or already enrolled and no valid X-OTP presented")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/2fa/enroll")]
+fn enroll(api_key: ApiKey, api_keys: &State<ApiKeys>, second_factors: &State<SecondFactors>) -> Result<Json<Enrollment>, Status> {
+    if !api_key.has_admin_scope() {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let owner = Owner::from(&api_key.principal);
+    let label = api_keys.identity(&api_key);
+    let secret = Secret::Raw(rand::random::<[u8; 20]>().to_vec()).to_encoded().to_string();
+    let provisioning_uri = totp(&secret, &label).ok_or(Status::InternalServerError)?.get_url();
+
+    let mut store = second_factors.store.lock().unwrap();
+    match store.get_mut(&owner) {
+        Some(factor) if factor.is_enrolled() && !api_key.has_second_factor() => return Err(api_keys.forbidden(&api_key)),
+        Some(factor) => {
+            factor.label = label;
+            factor.pending_secret = Some(secret.clone());
+        }
+        None => store.factors.push(Factor {
+            owner,
+            label,
+            secret: None,
+            pending_secret: Some(secret.clone()),
+            confirmed_at: None,
+            last_step: 0,
+            backup_codes: Vec::new(),
+            failed_attempts: 0,
+            locked_until: None,
+        }),
+    }
+    store.save()?;
+
+    Ok(Json(Enrollment { secret, provisioning_uri }))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/2fa/enroll/qr", tag = "two-factor",
+    responses((status = 200, description = "The pending provisioning URI as a QR code", content_type = "image/svg+xml", body = String), (status = 404, description = "No enrolment in progress")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[get("/api/v3/2fa/enroll/qr")]
+fn enroll_qr(api_key: ApiKey, second_factors: &State<SecondFactors>) -> Result<(ContentType, String), Status> {
+    let mut store = second_factors.store.lock().unwrap();
+    let factor = store.get_mut(&Owner::from(&api_key.principal)).ok_or(Status::NotFound)?;
+    let secret = factor.pending_secret.as_deref().ok_or(Status::NotFound)?;
+    let uri = totp(secret, &factor.label).ok_or(Status::InternalServerError)?.get_url();
+
+    let code = QrCode::new(uri.as_bytes()).map_err(|_| Status::InternalServerError)?;
+    let image = code.render::<svg::Color>().min_dimensions(256, 256).build();
+
+    Ok((ContentType::SVG, image))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/2fa/confirm", tag = "two-factor", request_body = CodeRequest,
+    responses((status = 200, description = "Enrolment is active, store the backup codes now", body = BackupCodes), (status = 404, description = "No enrolment in progress"), (status = 422, description = "Wrong code")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/2fa/confirm", data = "<request>")]
+fn confirm(request: Json<CodeRequest>, api_key: ApiKey, second_factors: &State<SecondFactors>) -> Result<Json<BackupCodes>, Status> {
+    let mut store = second_factors.store.lock().unwrap();
+    let factor = store.get_mut(&Owner::from(&api_key.principal)).ok_or(Status::NotFound)?;
+    let pending = factor.pending_secret.clone().ok_or(Status::NotFound)?;
+    let step = totp(&pending, &factor.label)
+        .and_then(|totp| matching_step(&totp, request.code.trim(), Utc::now()))
+        .ok_or(Status::UnprocessableEntity)?;
+
+    factor.secret = Some(pending);
+    factor.pending_secret = None;
+    factor.confirmed_at = Some(Utc::now());
+    factor.last_step = step;
+    let codes = factor.new_backup_codes();
+    store.save()?;
+
+    Ok(Json(BackupCodes { codes }))
+}
+
+// Browser sessions and access tokens step up once for a while, keys can also use this to test a code
+#[utoipa::path(
+    post, path = "/api/v3/2fa/verify", tag = "two-factor", request_body = CodeRequest,
+    responses((status = 200, body = SteppedUp), (status = 404, description = "No second factor enrolled"), (status = 422, description = "Wrong code"), (status = 429, description = "Locked after too many wrong codes, or rate limited")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/2fa/verify", data = "<request>")]
+fn verify(
+    request: Json<CodeRequest>,
+    api_key: ApiKey,
+    second_factors: &State<SecondFactors>,
+    accounts: &State<Accounts>,
+    tokens: &State<Tokens>,
+    _limitguard: RocketGovernor<RateLimitGuard>
+) -> Result<Json<SteppedUp>, Status> {
+    {
+        let mut store = second_factors.store.lock().unwrap();
+        let factor = store.get_mut(&Owner::from(&api_key.principal)).filter(|factor| factor.is_enrolled()).ok_or(Status::NotFound)?;
+        let verification = factor.verify(&request.code, Utc::now());
+        store.save()?;
+        match verification {
+            Verification::Accepted => (),
+            Verification::Rejected => {
+                metrics::METRICS.auth_failure("invalid_otp");
+                return Err(Status::UnprocessableEntity);
+            }
+            Verification::Locked(_) => {
+                metrics::METRICS.auth_failure("otp_locked");
+                return Err(Status::TooManyRequests);
+            }
+        }
+    }
+
+    let until = Utc::now() + Duration::minutes(STEP_UP_MINUTES);
+    let verified_until = match &api_key.principal {
+        Principal::Account { session, .. } => Some(accounts.step_up(session, until)?),
+        Principal::Token { family, .. } => Some(tokens.step_up(family, until)?),
+        Principal::Key(_) => None,
+    };
+    Ok(Json(SteppedUp { verified_until }))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/2fa/backup-codes", tag = "two-factor",
+    responses((status = 200, description = "A new set of backup codes, the old ones stop working", body = BackupCodes), (status = 403, description = "No valid X-OTP presented"), (status = 404)),
+    security(("bearer" = []), ("session" = []))
+)]
+#[post("/api/v3/2fa/backup-codes")]
+fn regenerate_backup_codes(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    second_factors: &State<SecondFactors>
+) -> Result<Json<BackupCodes>, Status> {
+    if !api_key.has_second_factor() {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = second_factors.store.lock().unwrap();
+    let factor = store.get_mut(&Owner::from(&api_key.principal)).filter(|factor| factor.is_enrolled()).ok_or(Status::NotFound)?;
+    let codes = factor.new_backup_codes();
+    store.save()?;
+
+    Ok(Json(BackupCodes { codes }))
+}
+
+// Without `key` or `user` removes the caller's own factor. Removing someone else's, e.g. after a
+// lost phone and lost backup codes, takes another admin
+#[utoipa::path(
+    delete, path = "/api/v3/2fa", tag = "two-factor",
+    params(
+        ("key" = Option<String>, Query, description = "Key id whose factor to remove"),
+        ("user" = Option<String>, Query, description = "Account id whose factor to remove")
+    ),
+    responses((status = 204, description = "Removed, admin actions need a new enrolment"), (status = 403), (status = 404), (status = 422, description = "Both key and user given")),
+    security(("bearer" = []), ("session" = []))
+)]
+#[delete("/api/v3/2fa?<key>&<user>")]
+fn remove(
+    key: Option<String>,
+    user: Option<String>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    second_factors: &State<SecondFactors>
+) -> Result<Status, Status> {
+    let owner = match (key, user) {
+        (None, None) => Owner::from(&api_key.principal),
+        (Some(key), None) => Owner::Key(key),
+        (None, Some(user)) => Owner::Account(user),
+        (Some(_), Some(_)) => return Err(Status::UnprocessableEntity),
+    };
+    let own = owner == Owner::from(&api_key.principal) && api_key.has_second_factor();
+    if !own && !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = second_factors.store.lock().unwrap();
+    let before = store.factors.len();
+    store.factors.retain(|factor| factor.owner != owner);
+    if store.factors.len() == before {
+        return Err(Status::NotFound);
+    }
+    store.save()?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![status, enroll, enroll_qr, confirm, verify, regenerate_backup_codes, remove]
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"; // "12345678901234567890", the RFC 6238 test key
+
+    fn now() -> DateTime<Utc> {
+        Utc.timestamp_opt(1_800_000_015, 0).unwrap()
+    }
+
+    fn factor() -> Factor {
+        Factor {
+            owner: Owner::Key("key1".to_string()),
+            label: "root".to_string(),
+            secret: Some(SECRET.to_string()),
+            pending_secret: None,
+            confirmed_at: Some(now()),
+            last_step: 0,
+            backup_codes: Vec::new(),
+            failed_attempts: 0,
+            locked_until: None,
+        }
+    }
+
+    fn code_at(at: DateTime<Utc>) -> String {
+        totp(SECRET, "root").unwrap().generate(at.timestamp() as u64)
+    }
+
+    #[test]
+    fn code_works_once() {
+        let mut factor = factor();
+        let code = code_at(now());
+
+        assert!(matches!(factor.verify(&code, now()), Verification::Accepted));
+        assert!(matches!(factor.verify(&code, now()), Verification::Rejected));
+        assert!(matches!(factor.verify(&code, now() + Duration::seconds(20)), Verification::Rejected));
+    }
+
+    #[test]
+    fn earlier_code_is_refused_after_a_later_one() {
+        let mut factor = factor();
+
+        assert!(matches!(factor.verify(&code_at(now()), now()), Verification::Accepted));
+        let previous = code_at(now() - Duration::seconds(STEP_SECONDS as i64));
+        assert!(matches!(factor.verify(&previous, now()), Verification::Rejected));
+    }
+
+    #[test]
+    fn one_step_of_clock_skew_is_tolerated() {
+        let step = Duration::seconds(STEP_SECONDS as i64);
+
+        assert!(matches!(factor().verify(&code_at(now() - step), now()), Verification::Accepted));
+        assert!(matches!(factor().verify(&code_at(now() + step), now()), Verification::Accepted));
+        assert!(matches!(factor().verify(&code_at(now() - step * 2), now()), Verification::Rejected));
+        assert!(matches!(factor().verify(&code_at(now() + step * 2), now()), Verification::Rejected));
+    }
+
+    #[test]
+    fn backup_codes_work_once() {
+        let mut factor = factor();
+        let codes = factor.new_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODES);
+
+        // Typed back with different case and spacing
+        let typed = codes[0].to_ascii_uppercase().replace('-', " ");
+        assert!(matches!(factor.verify(&typed, now()), Verification::Accepted));
+        assert!(matches!(factor.verify(&codes[0], now()), Verification::Rejected));
+        assert_eq!(factor.backup_codes.len(), BACKUP_CODES - 1);
+        assert!(matches!(factor.verify(&codes[1], now()), Verification::Accepted));
+    }
+
+    #[test]
+    fn repeated_wrong_codes_lock_the_factor() {
+        let mut factor = factor();
+        for _ in 0..FREE_ATTEMPTS {
+            assert!(matches!(factor.verify("not-a-code", now()), Verification::Rejected));
+        }
+
+        // Even the right code is refused while locked
+        let until = now() + Duration::seconds(LOCKOUT_SECONDS);
+        assert!(matches!(factor.verify(&code_at(now()), now()), Verification::Locked(at) if at == until));
+
+        let later = until + Duration::seconds(1);
+        assert!(matches!(factor.verify(&code_at(later), later), Verification::Accepted));
+        assert_eq!(factor.failed_attempts, 0);
+    }
+}
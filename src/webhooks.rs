@@ -0,0 +1,425 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::tokio::{self, sync::broadcast::{error::RecvError, Receiver}};
+use rocket::{Route, State};
+use sha2::Sha256;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::metrics::METRICS;
+use crate::stream::{LiveUpdate, LiveUpdates};
+use crate::{persistence, ApiKey, ApiKeys};
+
+const WEBHOOKS_FILE: &str = "webhooks.json";
+const MAX_ATTEMPTS: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEAD_LETTER_LIMIT: usize = 500;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct Subscriber {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    #[serde(default)]
+    pub events: Vec<String>, // event names, empty means every event
+    #[serde(default)]
+    pub kinds: Vec<String>, // update kinds such as "status_changed", empty means all
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct DeadLetter {
+    pub subscriber: String,
+    pub url: String,
+    pub kind: String,
+    pub payload: String,
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct WebhookStore {
+    subscribers: Vec<Subscriber>,
+    #[serde(default)]
+    dead_letters: Vec<DeadLetter>,
+}
+
+impl WebhookStore {
+    fn save(&self) -> Result<(), Status> {
+        persistence::save(WEBHOOKS_FILE, self).map_err(|_| Status::InternalServerError)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Payload<'a, T: Serialize> {
+    id: &'a str,
+    kind: &'a str,
+    occurred_at: DateTime<Utc>,
+    data: &'a T,
+}
+
+impl Subscriber {
+    fn wants(&self, update: &LiveUpdate) -> bool {
+        // Full snapshots are for stream clients, webhooks get the individual changes
+        if matches!(update, LiveUpdate::Events { .. }) {
+            return false;
+        }
+
+        let kind_matches = self.kinds.is_empty() || self.kinds.iter().any(|k| k == update.kind());
+        let event_matches = self.events.is_empty()
+            || match update {
+                LiveUpdate::StatusChanged { event, .. }
+                | LiveUpdate::EventAdded { event, .. }
+                | LiveUpdate::EventRemoved { event, .. }
+                | LiveUpdate::RegistrationsChanged { event, .. } => self.events.contains(event),
+                LiveUpdate::Announcement { announcement } => {
+                    announcement.events.is_empty()
+                        || announcement.events.iter().any(|e| self.events.contains(e))
+                }
+                _ => true,
+            };
+
+        kind_matches && event_matches
+    }
+}
+
+fn random_token(len: usize) -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(len).map(char::from).collect()
+}
+
+// Receivers verify HMAC-SHA256 over "<timestamp>.<body>" using their shared secret
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    store: Arc<Mutex<WebhookStore>>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn load() -> Self {
+        let store = persistence::load(WEBHOOKS_FILE).unwrap_or_default();
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        Webhooks { store: Arc::new(Mutex::new(store)), client }
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        persistence::save(WEBHOOKS_FILE, &*self.store.lock().unwrap())
+    }
+
+    async fn deliver_once(&self, subscriber: &Subscriber, kind: &str, id: &str, body: &str) -> Result<u16, String> {
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .client
+            .post(&subscriber.url)
+            .header("Content-Type", "application/json")
+            .header("X-Adharva-Event", kind)
+            .header("X-Adharva-Delivery", id)
+            .header("X-Adharva-Timestamp", timestamp.to_string())
+            .header("X-Adharva-Signature", format!("sha256={}", sign(&subscriber.secret, timestamp, body)))
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(status.as_u16())
+        } else {
+            Err(format!("receiver responded with {}", status))
+        }
+    }
+
+    async fn deliver(self, subscriber: Subscriber, kind: String, id: String, body: String) {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut last_error = String::new();
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.deliver_once(&subscriber, &kind, &id, &body).await {
+                Ok(_) => return,
+                Err(error) => {
+                    warn!(subscriber = %subscriber.id, delivery = %id, attempt, %error, "webhook delivery failed");
+                    last_error = error;
+                }
+            }
+            if attempt < MAX_ATTEMPTS {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        let mut store = self.store.lock().unwrap();
+        store.dead_letters.push(DeadLetter {
+            subscriber: subscriber.id.clone(),
+            url: subscriber.url.clone(),
+            kind,
+            payload: body,
+            attempts: MAX_ATTEMPTS,
+            last_error,
+            failed_at: Utc::now(),
+        });
+        let overflow = store.dead_letters.len().saturating_sub(DEAD_LETTER_LIMIT);
+        store.dead_letters.drain(..overflow);
+        if let Err(error) = store.save() {
+            warn!(subscriber = %subscriber.id, delivery = %id, %error, "could not save the webhook dead letter list");
+        }
+    }
+
+    async fn dispatch(self, mut rx: Receiver<LiveUpdate>) {
+        loop {
+            let update = match rx.recv().await {
+                Ok(update) => update,
+                // The skipped updates are gone for good, so at least leave a trace of the gap
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(missed, "webhook dispatcher fell behind, updates were not delivered");
+                    METRICS.updates_missed("webhooks", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let subscribers: Vec<Subscriber> = {
+                let store = self.store.lock().unwrap();
+                store.subscribers.iter().filter(|s| s.wants(&update)).cloned().collect()
+            };
+            if subscribers.is_empty() {
+                continue;
+            }
+
+            let id = random_token(20);
+            let payload = Payload { id: &id, kind: update.kind(), occurred_at: Utc::now(), data: &update };
+            let body = match serde_json::to_string(&payload) {
+                Ok(body) => body,
+                Err(_) => continue,
+            };
+
+            for subscriber in subscribers {
+                let kind = update.kind().to_string();
+                tokio::spawn(self.clone().deliver(subscriber, kind, id.clone(), body.clone()));
+            }
+        }
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Webhook dispatcher", |rocket| Box::pin(async move {
+        let webhooks = rocket.state::<Webhooks>().expect("Webhooks not managed").clone();
+        let rx = rocket.state::<LiveUpdates>().expect("LiveUpdates not managed").subscribe();
+        tokio::spawn(webhooks.dispatch(rx));
+    }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct NewSubscriber {
+    url: String,
+    secret: Option<String>,
+    #[serde(default)]
+    events: Vec<String>,
+    #[serde(default)]
+    kinds: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(crate = "rocket::serde")]
+pub struct TestResult {
+    delivered: bool,
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/webhooks", tag = "webhooks",
+    responses((status = 200, body = [Subscriber]), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/webhooks")]
+fn list_webhooks(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Json<Vec<Subscriber>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    Ok(Json(webhooks.store.lock().unwrap().subscribers.clone()))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/webhooks", tag = "webhooks", request_body = NewSubscriber,
+    responses((status = 200, body = Subscriber), (status = 403), (status = 422)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/webhooks", data = "<subscriber>")]
+fn create_webhook(
+    subscriber: Json<NewSubscriber>,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Json<Subscriber>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let subscriber = subscriber.into_inner();
+    match reqwest::Url::parse(&subscriber.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => (),
+        _ => return Err(Status::UnprocessableEntity),
+    }
+
+    let created = Subscriber {
+        id: random_token(12),
+        url: subscriber.url,
+        secret: subscriber.secret.filter(|s| !s.is_empty()).unwrap_or_else(|| random_token(32)),
+        events: subscriber.events,
+        kinds: subscriber.kinds,
+        created_at: Utc::now(),
+    };
+
+    let mut store = webhooks.store.lock().unwrap();
+    store.subscribers.push(created.clone());
+    store.save()?;
+
+    Ok(Json(created))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/webhooks/{id}", tag = "webhooks",
+    params(("id" = String, Path, description = "Subscriber id")),
+    responses((status = 200, body = Subscriber), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/webhooks/<id>")]
+fn delete_webhook(
+    id: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Json<Subscriber>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = webhooks.store.lock().unwrap();
+    let index = store.subscribers.iter().position(|s| s.id == id).ok_or(Status::NotFound)?;
+    let removed = store.subscribers.remove(index);
+    store.save()?;
+
+    Ok(Json(removed))
+}
+
+#[utoipa::path(
+    post, path = "/api/v3/admin/webhooks/{id}/test", tag = "webhooks",
+    params(("id" = String, Path, description = "Subscriber id")),
+    responses((status = 200, body = TestResult), (status = 403), (status = 404)),
+    security(("bearer" = []))
+)]
+#[post("/api/v3/admin/webhooks/<id>/test")]
+async fn test_webhook(
+    id: &str,
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Json<TestResult>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let subscriber = webhooks
+        .store
+        .lock()
+        .unwrap()
+        .subscribers
+        .iter()
+        .find(|s| s.id == id)
+        .cloned()
+        .ok_or(Status::NotFound)?;
+
+    let delivery_id = random_token(20);
+    let data = serde_json::json!({ "message": "Test delivery from the Adharva event server" });
+    let payload = Payload { id: &delivery_id, kind: "test", occurred_at: Utc::now(), data: &data };
+    let body = serde_json::to_string(&payload).map_err(|_| Status::InternalServerError)?;
+
+    // Test fires are sent once without retries so the result can be reported directly
+    let result = match webhooks.deliver_once(&subscriber, "test", &delivery_id, &body).await {
+        Ok(status) => TestResult { delivered: true, status: Some(status), error: None },
+        Err(error) => TestResult { delivered: false, status: None, error: Some(error) },
+    };
+
+    Ok(Json(result))
+}
+
+#[utoipa::path(
+    get, path = "/api/v3/admin/webhooks/dead-letters", tag = "webhooks",
+    responses((status = 200, body = [DeadLetter]), (status = 403)),
+    security(("bearer" = []))
+)]
+#[get("/api/v3/admin/webhooks/dead-letters")]
+fn dead_letters(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Json<Vec<DeadLetter>>, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+    Ok(Json(webhooks.store.lock().unwrap().dead_letters.clone()))
+}
+
+#[utoipa::path(
+    delete, path = "/api/v3/admin/webhooks/dead-letters", tag = "webhooks",
+    responses((status = 204), (status = 403)),
+    security(("bearer" = []))
+)]
+#[delete("/api/v3/admin/webhooks/dead-letters")]
+fn clear_dead_letters(
+    api_key: ApiKey,
+    api_keys: &State<ApiKeys>,
+    webhooks: &State<Webhooks>
+) -> Result<Status, Status> {
+    if !api_keys.is_root(&api_key) {
+        return Err(api_keys.forbidden(&api_key));
+    }
+
+    let mut store = webhooks.store.lock().unwrap();
+    store.dead_letters.clear();
+    store.save()?;
+
+    Ok(Status::NoContent)
+}
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        list_webhooks,
+        create_webhook,
+        delete_webhook,
+        test_webhook,
+        dead_letters,
+        clear_dead_letters
+    ]
+}
@@ -0,0 +1,273 @@
+"use strict";
+
+// A key is swapped for short-lived tokens right away and only those are kept, in sessionStorage so
+// closing the tab logs the coordinator out. Password logins use an HttpOnly session cookie instead,
+// with the CSRF token kept in memory and fetched again on reload
+const TOKEN_STORAGE = "adharva-tokens";
+let csrfToken = null;
+let refreshing = null;
+
+const storedTokens = () => JSON.parse(sessionStorage.getItem(TOKEN_STORAGE) || "null");
+const $ = id => document.getElementById(id);
+
+const label = status => status.replace(/^Round(\d)$/, "Round $1");
+
+const messages = {
+    401: "That key was not recognised.",
+    403: "You are not allowed to change that event.",
+    423: "Statuses are locked by an emergency override.",
+    429: "Too many requests, wait a second and try again.",
+};
+
+// Refresh tokens are single use, so concurrent requests share one refresh
+function refreshTokens(tokens) {
+    refreshing ??= fetch("/api/v3/token/refresh", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ refresh_token: tokens.refresh_token }),
+    })
+        .then(async response => {
+            if (!response.ok) return false;
+            sessionStorage.setItem(TOKEN_STORAGE, JSON.stringify(await response.json()));
+            return true;
+        })
+        .catch(() => false)
+        .finally(() => (refreshing = null));
+    return refreshing;
+}
+
+async function api(path, options = {}, retried = false) {
+    const tokens = storedTokens();
+    const headers = tokens ? { Authorization: "Bearer " + tokens.access_token } : { "X-CSRF-Token": csrfToken || "" };
+    if (options.body) headers["Content-Type"] = "application/json";
+
+    const response = await fetch(path, { ...options, headers });
+    if (response.status === 401 && tokens && !retried) {
+        // Another request may have refreshed already, otherwise swap the refresh token and retry once
+        const current = storedTokens();
+        if (current && (current.access_token !== tokens.access_token || await refreshTokens(current))) {
+            return api(path, options, true);
+        }
+    }
+
+    // Admin actions need a second factor, which both sessions and tokens enter once every 15 minutes
+    const stepUp = response.headers.get("X-OTP-Required");
+    if (stepUp === "code" && !options.steppedUp) {
+        const code = (prompt("Enter the code from your authenticator app, or a backup code.") || "").trim();
+        if (code) {
+            await api("/api/v3/2fa/verify", { method: "POST", body: JSON.stringify({ code }), steppedUp: true }).catch(() => {});
+            return api(path, { ...options, steppedUp: true }, retried);
+        }
+    }
+    if (stepUp === "locked") {
+        throw Object.assign(new Error("Too many wrong codes, admin actions are locked for a while."), { status: 403 });
+    }
+    if (stepUp === "enroll") {
+        throw Object.assign(new Error("Admin actions need a second factor, enrol one with `adharva 2fa enroll`."), { status: 403 });
+    }
+    if (!response.ok) {
+        throw Object.assign(new Error(messages[response.status] || "Server responded with " + response.status), {
+            status: response.status,
+        });
+    }
+    return response.status === 204 ? null : response.json();
+}
+
+function el(tag, props = {}, children = []) {
+    const node = Object.assign(document.createElement(tag), props);
+    node.append(...children);
+    return node;
+}
+
+function showError(error) {
+    $("error").textContent = error ? error.message : "";
+    if (error && error.status === 401) logout();
+}
+
+let editable = [];
+
+async function loadEvents() {
+    try {
+        editable = await api("/api/v3/dashboard/events");
+        showError(null);
+    } catch (error) {
+        return showError(error);
+    }
+
+    $("events").replaceChildren(...editable.map(event => {
+        const buttons = event.transitions.map(status => el("button", {
+            textContent: label(status),
+            className: status === "Ended" ? "danger" : "",
+            onclick: () => setStatus(event.name, status),
+        }));
+        if (!buttons.length) buttons.push(el("p", { textContent: "No further steps." }));
+
+        return el("div", { className: "card" }, [
+            el("h2", {}, [event.name, el("span", { className: "badge " + event.tone, textContent: event.label })]),
+            el("div", { className: "actions" }, buttons),
+        ]);
+    }));
+    if (!editable.length) $("events").textContent = "You cannot edit any events.";
+}
+
+async function setStatus(eventName, status) {
+    if (status === "Ended" && !confirm(`Mark ${eventName} as Ended? Participants will see it as finished.`)) {
+        return;
+    }
+
+    document.querySelectorAll(".actions button").forEach(button => (button.disabled = true));
+    try {
+        await api(`/api/v3/update/${encodeURIComponent(eventName)}/${status}`, { method: "POST" });
+        showError(null);
+    } catch (error) {
+        showError(error);
+    }
+    await loadEvents();
+}
+
+async function loadHistory() {
+    const names = new Set(editable.map(event => event.name));
+    try {
+        const entries = await api("/api/v3/history?limit=200");
+        $("history").tBodies[0].replaceChildren(...entries
+            .filter(entry => names.has(entry.event))
+            .map(entry => el("tr", {}, [
+                el("td", { textContent: new Date(entry.at).toLocaleString([], { day: "numeric", month: "short", hour: "2-digit", minute: "2-digit" }) }),
+                el("td", { textContent: entry.event }),
+                el("td", { textContent: entry.change === "added" ? `Added as ${label(entry.to)}`
+                    : entry.change === "removed" ? "Removed" : `${label(entry.from)} → ${label(entry.to)}` }),
+                el("td", { textContent: entry.by }),
+            ])));
+        showError(null);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+let admin = false;
+
+// Admins see every session, so a lost phone can be logged out for its owner
+async function loadSessions() {
+    try {
+        const sessions = await api(admin ? "/api/v3/admin/sessions" : "/api/v3/sessions");
+        $("sessions").tBodies[0].replaceChildren(...sessions.map(session => el("tr", {}, [
+            el("td", { textContent: session.username }),
+            el("td", { textContent: new Date(session.last_seen_at).toLocaleString([], { day: "numeric", month: "short", hour: "2-digit", minute: "2-digit" }) }),
+            el("td", { className: "device", textContent: session.user_agent || session.ip || "", title: [session.user_agent, session.ip].filter(Boolean).join(" from ") }),
+            el("td", {}, [session.current ? "This device" : el("button", {
+                className: "link",
+                textContent: "Log out",
+                onclick: () => endSession(session),
+            })]),
+        ])));
+        showError(null);
+    } catch (error) {
+        showError(error);
+    }
+}
+
+async function endSession(session) {
+    if (!confirm(`Log out ${session.username} on ${session.user_agent || "that device"}?`)) return;
+    try {
+        await api(`/api/v3/sessions/${encodeURIComponent(session.id)}`, { method: "DELETE" });
+    } catch (error) {
+        showError(error);
+    }
+    await loadSessions();
+}
+
+function showTab(tab) {
+    document.querySelectorAll("nav button").forEach(button => button.classList.toggle("active", button.dataset.tab === tab));
+    $("events").hidden = tab !== "events";
+    $("history").hidden = tab !== "history";
+    $("sessions").hidden = tab !== "sessions";
+    if (tab === "history") loadHistory(); else if (tab === "sessions") loadSessions(); else loadEvents();
+}
+
+function showLogin(message) {
+    sessionStorage.removeItem(TOKEN_STORAGE);
+    csrfToken = null;
+    $("login-error").textContent = message;
+    $("login").hidden = false;
+}
+
+async function start() {
+    let whoami;
+    try {
+        if (storedTokens()) {
+            whoami = await api("/api/v3/whoami");
+        } else {
+            const session = await api("/api/v3/session");
+            csrfToken = session.csrf_token;
+            whoami = session.account;
+        }
+    } catch (error) {
+        // No session cookie just means nobody has logged in yet
+        return showLogin(error.status === 401 && !storedTokens() ? "" : error.message);
+    }
+
+    admin = whoami.admin;
+
+    $("identity").textContent = whoami.admin ? "root" : whoami.identity;
+    $("login").hidden = true;
+    $("logout").hidden = false;
+    $("dashboard").hidden = false;
+    showTab("events");
+}
+
+async function logout() {
+    const tokens = storedTokens();
+    if (tokens) {
+        await fetch("/api/v3/token/revoke", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ refresh_token: tokens.refresh_token }),
+        }).catch(() => {});
+    } else if (csrfToken) {
+        // The cookie is HttpOnly, so only the server can remove it
+        await api("/api/v3/logout", { method: "POST" }).catch(() => {});
+    }
+    sessionStorage.removeItem(TOKEN_STORAGE);
+    location.reload();
+}
+
+$("login").addEventListener("submit", async event => {
+    event.preventDefault();
+    const key = $("key").value.trim();
+    if (key) {
+        $("key").value = "";
+        const response = await fetch("/api/v3/token", { method: "POST", headers: { Authorization: "Bearer " + key } });
+        if (!response.ok) {
+            $("login-error").textContent = messages[response.status] || "Server responded with " + response.status;
+            return;
+        }
+        sessionStorage.setItem(TOKEN_STORAGE, JSON.stringify(await response.json()));
+        return start();
+    }
+
+    try {
+        const session = await api("/api/v3/login", {
+            method: "POST",
+            body: JSON.stringify({ username: $("username").value.trim(), password: $("password").value }),
+        });
+        csrfToken = session.csrf_token;
+    } catch (error) {
+        $("login-error").textContent = error.status === 401 ? "Wrong username or password." : error.message;
+        return;
+    } finally {
+        $("password").value = "";
+    }
+    start();
+});
+$("logout").addEventListener("click", logout);
+document.querySelectorAll("nav button").forEach(button => button.addEventListener("click", () => showTab(button.dataset.tab)));
+
+// Keep the buttons in step with changes made by other coordinators
+const stream = new EventSource("/api/v3/stream");
+for (const kind of ["status_changed", "event_added", "event_removed"]) {
+    stream.addEventListener(kind, () => {
+        if (!$("dashboard").hidden && $("sessions").hidden) showTab($("history").hidden ? "events" : "history");
+    });
+}
+
+start();